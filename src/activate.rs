@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+
+use crate::opt::{AppVersions, Shell};
+
+/// Dirs `up` put in front of `PATH`, so they can be taken out again.
+const ACTIVE_PATH: &str = "UP_ACTIVE_PATH";
+
+/// The hook which runs `up activate <shell> --env` before every prompt.
+pub fn hook(shell: Shell) -> anyhow::Result<String> {
+    let exe = std::env::current_exe()?;
+    let exe = quote(shell, &exe.to_string_lossy());
+    let hook = match shell {
+        Shell::Bash => format!(
+            r#"_up_hook() {{
+  local previous_status=$?
+  eval "$({exe} activate bash --env)"
+  return $previous_status
+}}
+if [[ ";${{PROMPT_COMMAND[*]:-}};" != *";_up_hook;"* ]]; then
+  PROMPT_COMMAND="_up_hook${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}"
+fi
+"#,
+            exe = exe
+        ),
+        Shell::Zsh => format!(
+            r#"_up_hook() {{
+  eval "$({exe} activate zsh --env)"
+}}
+typeset -ag precmd_functions chpwd_functions
+if (( ! ${{precmd_functions[(I)_up_hook]}} )); then
+  precmd_functions=(_up_hook $precmd_functions)
+fi
+if (( ! ${{chpwd_functions[(I)_up_hook]}} )); then
+  chpwd_functions=(_up_hook $chpwd_functions)
+fi
+"#,
+            exe = exe
+        ),
+        Shell::Fish => format!(
+            r#"function _up_hook --on-variable PWD --on-event fish_prompt
+    {exe} activate fish --env | source
+end
+"#,
+            exe = exe
+        ),
+    };
+    Ok(hook)
+}
+
+/// Commands which put the bin dirs of the versions pinned by the project `dir` is in
+/// first in `PATH`, taking out those put there before. `None` if nothing changes.
+///
+/// Only the lock is read, it runs before every prompt.
+pub fn env(shell: Shell, dir: &Path, locked_versions: &AppVersions) -> Option<String> {
+    let active: Vec<PathBuf> = std::env::var_os(ACTIVE_PATH)
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default();
+    let dirs = bin_dirs(dir, locked_versions);
+    if dirs == active {
+        return None;
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let path: Vec<PathBuf> = dirs
+        .iter()
+        .cloned()
+        .chain(std::env::split_paths(&path).filter(|path| !active.contains(path)))
+        .collect();
+
+    let mut commands = String::new();
+    match shell {
+        Shell::Bash | Shell::Zsh => {
+            let path = std::env::join_paths(path).ok()?;
+            commands += &format!("export PATH={};\n", quote(shell, &path.to_string_lossy()));
+            if dirs.is_empty() {
+                commands += &format!("unset {};\n", ACTIVE_PATH);
+            } else {
+                let dirs = std::env::join_paths(&dirs).ok()?;
+                commands += &format!(
+                    "export {}={};\n",
+                    ACTIVE_PATH,
+                    quote(shell, &dirs.to_string_lossy())
+                );
+            }
+        }
+        Shell::Fish => {
+            let path: Vec<_> = path
+                .iter()
+                .map(|path| quote(shell, &path.to_string_lossy()))
+                .collect();
+            commands += &format!("set -gx PATH {};\n", path.join(" "));
+            if dirs.is_empty() {
+                commands += &format!("set -e {};\n", ACTIVE_PATH);
+            } else {
+                let dirs = std::env::join_paths(&dirs).ok()?;
+                commands += &format!(
+                    "set -gx {} {};\n",
+                    ACTIVE_PATH,
+                    quote(shell, &dirs.to_string_lossy())
+                );
+            }
+        }
+    }
+    Some(commands)
+}
+
+/// The dirs of the executables of the installed versions the project `dir` is in pins.
+///
+/// Pinned versions which are not installed yet are left to `up sync`.
+fn bin_dirs(dir: &Path, locked_versions: &AppVersions) -> Vec<PathBuf> {
+    let project = match crate::project::find(dir) {
+        Ok(Some((_, project))) => project,
+        _ => return Vec::new(),
+    };
+    let mut dirs = Vec::new();
+    for (app, pinned) in project.apps.iter() {
+        let installed = match locked_versions.version(app, &pinned.version) {
+            Some(installed) => installed,
+            None => continue,
+        };
+        for file in installed.files.iter() {
+            if let Some(parent) = file.parent() {
+                if !dirs.iter().any(|dir| dir == parent) {
+                    dirs.push(parent.to_path_buf());
+                }
+            }
+        }
+    }
+    dirs
+}
+
+fn quote(shell: Shell, s: &str) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("'{}'", s.replace('\'', r"'\''")),
+        Shell::Fish => format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'")),
+    }
+}
@@ -1,5 +1,3 @@
-use std::path::Path;
-
 #[derive(Debug)]
 pub enum ArchiveFormat {
     Z,
@@ -0,0 +1,95 @@
+use std::path::Path;
+
+use crate::ghapi::GhRepo;
+use crate::opt::{Attestation, AttestationConfig};
+
+/// Issuer of the certificates of GitHub Actions workflows.
+const GITHUB_ACTIONS_ISSUER: &str = "https://token.actions.githubusercontent.com";
+
+/// Verifies the artifact attestation of `file` published in `repo`.
+///
+/// The Sigstore bundle is checked by `cosign` against the local trusted root, so nothing
+/// but the bundle is fetched, and the certificate must be issued to a workflow matching
+/// `cfg.workflow`. Fails only if the attestation is `required`.
+pub async fn verify(
+    cfg: &AttestationConfig,
+    repo: &GhRepo,
+    file: &Path,
+) -> anyhow::Result<Attestation> {
+    match attest(cfg, repo, file).await {
+        Ok(workflow) => Ok(Attestation {
+            verified: true,
+            workflow,
+            reason: None,
+        }),
+        Err(err) if !cfg.required => Ok(Attestation {
+            verified: false,
+            workflow: None,
+            reason: Some(format!("{:#}", err)),
+        }),
+        Err(err) => Err(err),
+    }
+}
+
+/// The workflow of the first bundle which verifies.
+async fn attest(
+    cfg: &AttestationConfig,
+    repo: &GhRepo,
+    file: &Path,
+) -> anyhow::Result<Option<String>> {
+    let trusted_root = crate::signature::trusted_root(cfg.trusted_root.as_deref())?;
+    let digest = format!("sha256:{}", crate::checksum::sha256(file)?);
+    let bundles = repo
+        .attestations(&digest)
+        .await
+        .map_err(|err| anyhow::anyhow!(err))?;
+    anyhow::ensure!(
+        !bundles.is_empty(),
+        "no attestation of {} in {}",
+        &digest,
+        repo.full_name()
+    );
+    let mut last = None;
+    for bundle in bundles.iter() {
+        let mut path = file.as_os_str().to_owned();
+        path.push(".sigstore.json");
+        let path = std::path::PathBuf::from(path);
+        tokio::fs::write(&path, serde_json::to_vec(bundle)?).await?;
+        let args: [&std::ffi::OsStr; 13] = [
+            "verify-blob-attestation".as_ref(),
+            "--new-bundle-format".as_ref(),
+            "--bundle".as_ref(),
+            path.as_os_str(),
+            "--trusted-root".as_ref(),
+            trusted_root.as_os_str(),
+            "--certificate-identity-regexp".as_ref(),
+            cfg.workflow.as_ref(),
+            "--certificate-oidc-issuer".as_ref(),
+            GITHUB_ACTIONS_ISSUER.as_ref(),
+            "--type".as_ref(),
+            "slsaprovenance1".as_ref(),
+            file.as_os_str(),
+        ];
+        let verified = crate::signature::run("cosign", &args).await;
+        tokio::fs::remove_file(&path).await.ok();
+        match verified {
+            Ok(_) => return Ok(workflow(bundle)),
+            Err(err) => last = Some(err),
+        }
+    }
+    Err(last.unwrap())
+}
+
+/// `{repository}/{path}@{ref}` of the workflow in the SLSA provenance of `bundle`.
+fn workflow(bundle: &serde_json::Value) -> Option<String> {
+    let payload = bundle.pointer("/dsseEnvelope/payload")?.as_str()?;
+    let statement: serde_json::Value =
+        serde_json::from_slice(&base64::decode(payload).ok()?).ok()?;
+    let workflow = statement.pointer("/predicate/buildDefinition/externalParameters/workflow")?;
+    Some(format!(
+        "{}/{}@{}",
+        workflow.get("repository")?.as_str()?,
+        workflow.get("path")?.as_str()?,
+        workflow.get("ref")?.as_str()?
+    ))
+}
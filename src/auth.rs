@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+/// Environment variables holding a token for github.com, in order of precedence.
+const TOKEN_VARS: [&str; 2] = ["GITHUB_TOKEN", "GH_TOKEN"];
+
+/// Same as `TOKEN_VARS`, but for GitHub Enterprise Server hosts.
+const ENTERPRISE_TOKEN_VARS: [&str; 2] = ["GH_ENTERPRISE_TOKEN", "GITHUB_ENTERPRISE_TOKEN"];
+
+/// Token to authorize API requests to `host`.
+///
+/// Looked up in `GITHUB_TOKEN`/`GH_TOKEN` (`GH_ENTERPRISE_TOKEN`/`GITHUB_ENTERPRISE_TOKEN`
+/// for the `enterprise` hosts configured in `up.toml`), then the `configured` one in
+/// `up.toml`, then the `hosts.yml` of the `gh` CLI.
+///
+/// The enterprise token never goes to hosts which are not configured, a repo on any host
+/// may be installed.
+pub fn token(host: &str, configured: Option<&str>, enterprise: bool) -> Option<String> {
+    let vars: &[&str] = if host == crate::opt::GITHUB {
+        &TOKEN_VARS
+    } else if enterprise {
+        &ENTERPRISE_TOKEN_VARS
+    } else {
+        &[]
+    };
+    vars.iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|token| !token.is_empty())
+        .or_else(|| configured.map(String::from))
+        .or_else(|| gh_token(host))
+}
+
+fn gh_config_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("GH_CONFIG_DIR") {
+        return Some(dir.into());
+    }
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("gh"));
+    }
+    directories::BaseDirs::new().map(|dirs| dirs.home_dir().join(".config").join("gh"))
+}
+
+/// The `oauth_token` of `host` from the `hosts.yml` written by `gh auth login`.
+fn gh_token(host: &str) -> Option<String> {
+    let hosts = std::fs::read_to_string(gh_config_dir()?.join("hosts.yml")).ok()?;
+    oauth_token(&hosts, host)
+}
+
+/// A tiny reader for `hosts.yml`, which looks like
+///
+/// ```yaml
+/// github.com:
+///     user: someone
+///     oauth_token: gho_xxxx
+/// ```
+fn oauth_token(hosts: &str, host: &str) -> Option<String> {
+    let mut lines = hosts
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .skip_while(|line| unquote(line.trim_end().trim_end_matches(':')) != host);
+    lines.next()?;
+    let mut indent = None;
+    for line in lines {
+        let current = line.len() - line.trim_start().len();
+        if current == 0 {
+            break;
+        }
+        // Only direct children of the host, not those of `users:`.
+        if *indent.get_or_insert(current) != current {
+            continue;
+        }
+        if let Some(token) = line.trim().strip_prefix("oauth_token:") {
+            let token = unquote(token.trim());
+            if !token.is_empty() {
+                return Some(token.to_string());
+            }
+        }
+    }
+    None
+}
+
+fn unquote(s: &str) -> &str {
+    s.trim_matches(|c| c == '"' || c == '\'')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTS: &str = r#"
+github.com:
+    user: someone
+    oauth_token: gho_public
+    git_protocol: https
+    users:
+        other:
+            oauth_token: gho_other
+"ghe.example.com":
+    # managed by gh
+    oauth_token: 'gho_enterprise'
+empty.example.com:
+    oauth_token: ""
+"#;
+
+    #[test]
+    fn oauth_token_of_host() {
+        let cases = [
+            ("github.com", Some("gho_public")),
+            ("ghe.example.com", Some("gho_enterprise")),
+            ("empty.example.com", None),
+            ("missing.example.com", None),
+            ("other", None),
+        ];
+        for (host, expected) in cases {
+            assert_eq!(oauth_token(HOSTS, host).as_deref(), expected, "{}", host);
+        }
+    }
+
+    #[test]
+    fn oauth_token_of_user_only() {
+        let hosts = "github.com:\n    users:\n        someone:\n            oauth_token: gho_x\n";
+        assert_eq!(oauth_token(hosts, "github.com"), None);
+    }
+}
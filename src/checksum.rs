@@ -0,0 +1,300 @@
+use std::io::Read;
+use std::path::Path;
+
+use sha2::{Digest, Sha256, Sha512};
+
+/// Hash algorithms of published checksums.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha256" => Some(Algorithm::Sha256),
+            "sha512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Guesses the algorithm of a hex encoded hash from its length.
+    fn of_hex(hex: &str) -> Option<Algorithm> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        match hex.len() {
+            64 => Some(Algorithm::Sha256),
+            128 => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+fn hash_with<D: Digest>(path: &Path) -> anyhow::Result<String> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|err| anyhow::anyhow!(err).context(format!("Can not read {}", path.display())))?;
+    let mut hasher = D::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Hex encoded hash of the file at `path`.
+pub fn hash(path: impl AsRef<Path>, algorithm: Algorithm) -> anyhow::Result<String> {
+    match algorithm {
+        Algorithm::Sha256 => hash_with::<Sha256>(path.as_ref()),
+        Algorithm::Sha512 => hash_with::<Sha512>(path.as_ref()),
+    }
+}
+
+/// Hex encoded sha256 of the file at `path`.
+pub fn sha256(path: impl AsRef<Path>) -> anyhow::Result<String> {
+    hash(path, Algorithm::Sha256)
+}
+
+/// Digest of the file at `path` as `<algorithm>:<hex>`, like GitHub shows them.
+pub fn digest(path: impl AsRef<Path>, algorithm: Algorithm) -> anyhow::Result<String> {
+    Ok(format!("{}:{}", algorithm.name(), hash(path, algorithm)?))
+}
+
+/// Whether the algorithm of `digest`, as `<algorithm>:<hex>`, is one `verify` knows.
+pub fn is_supported(digest: &str) -> bool {
+    digest
+        .split_once(':')
+        .and_then(|(algorithm, _)| Algorithm::from_name(algorithm))
+        .is_some()
+}
+
+/// Checks the file at `path` against `expected`, a digest as `<algorithm>:<hex>`.
+pub fn verify(path: impl AsRef<Path>, expected: &str) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let (algorithm, hex) = expected
+        .split_once(':')
+        .and_then(|(algorithm, hex)| Some((Algorithm::from_name(algorithm)?, hex)))
+        .ok_or_else(|| anyhow::anyhow!("unsupported digest: {}", expected))?;
+    let actual = hash(path, algorithm)?;
+    anyhow::ensure!(
+        actual.eq_ignore_ascii_case(hex),
+        "checksum mismatch of {}: expected {}, got {}:{}",
+        path.display(),
+        expected,
+        algorithm.name(),
+        actual
+    );
+    Ok(())
+}
+
+/// Hashes bytes as they are streamed and checks them against a digest as
+/// `<algorithm>:<hex>`.
+pub struct Verifier {
+    expected: String,
+    hasher: Hasher,
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Verifier {
+    pub fn new(expected: &str) -> anyhow::Result<Verifier> {
+        let hasher = match expected
+            .split_once(':')
+            .and_then(|(algorithm, _)| Algorithm::from_name(algorithm))
+        {
+            Some(Algorithm::Sha256) => Hasher::Sha256(Sha256::new()),
+            Some(Algorithm::Sha512) => Hasher::Sha512(Sha512::new()),
+            None => anyhow::bail!("unsupported digest: {}", expected),
+        };
+        Ok(Verifier {
+            expected: expected.to_string(),
+            hasher,
+        })
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match &mut self.hasher {
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+            Hasher::Sha512(hasher) => hasher.update(bytes),
+        }
+    }
+
+    /// Checks the bytes streamed so far.
+    pub fn finish(self) -> anyhow::Result<()> {
+        let (algorithm, hash) = match self.hasher {
+            Hasher::Sha256(hasher) => (Algorithm::Sha256, hasher.finalize().to_vec()),
+            Hasher::Sha512(hasher) => (Algorithm::Sha512, hasher.finalize().to_vec()),
+        };
+        let actual: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let hex = self.expected.split_once(':').map_or("", |(_, hex)| hex);
+        anyhow::ensure!(
+            actual.eq_ignore_ascii_case(hex),
+            "checksum mismatch: expected {}, got {}:{}",
+            &self.expected,
+            algorithm.name(),
+            actual
+        );
+        Ok(())
+    }
+}
+
+/// Whether the asset `name` publishes checksums of the asset `of`, either as a checksum
+/// list like `SHA256SUMS` or `checksums.txt`, or next to it like `{of}.sha256`.
+pub fn is_checksums_of(name: &str, of: &str) -> bool {
+    if let Some(ext) = name
+        .strip_prefix(of)
+        .and_then(|rest| rest.strip_prefix('.'))
+    {
+        return matches!(
+            ext.to_ascii_lowercase().as_str(),
+            "sha256" | "sha512" | "sha256sum" | "sha512sum"
+        );
+    }
+    let name = name.to_ascii_lowercase();
+    let stem = name.strip_suffix(".txt").unwrap_or(&name);
+    stem.ends_with("checksums") || stem.ends_with("sha256sums") || stem.ends_with("sha512sums")
+}
+
+/// The digest of `name` from the content of a checksum asset, as `<algorithm>:<hex>`.
+///
+/// Understands `<hex>  <name>` and `<hex> *<name>` lines of `sha256sum`, BSD style
+/// `SHA256 (<name>) = <hex>` lines, and a lone `<hex>` of a per-asset checksum file.
+pub fn find(content: &str, name: &str) -> Option<String> {
+    let lines: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    for line in lines.iter() {
+        if let Some((algorithm, rest)) = line.split_once(" (") {
+            if let Some((file, hex)) = rest.split_once(") = ") {
+                let algorithm = Algorithm::from_name(algorithm);
+                if file == name && algorithm.is_some() && Algorithm::of_hex(hex.trim()) == algorithm
+                {
+                    return Some(format!("{}:{}", algorithm?.name(), hex.trim()));
+                }
+            }
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let hex = parts.next()?;
+        let file = parts.next().map(|file| file.trim_start_matches('*'));
+        let file = file.map(|file| file.rsplit('/').next().unwrap_or(file));
+        match (Algorithm::of_hex(hex), file) {
+            (Some(algorithm), Some(file)) if file == name => {
+                return Some(format!("{}:{}", algorithm.name(), hex));
+            }
+            (Some(algorithm), None) if lines.len() == 1 => {
+                return Some(format!("{}:{}", algorithm.name(), hex));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX256: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn find_digest_of_name() {
+        let hex512 = "ab".repeat(64);
+        let cases = [
+            (
+                format!("{}  tool.tar.gz\n", HEX256),
+                Some(format!("sha256:{}", HEX256)),
+            ),
+            (
+                format!("{} *tool.tar.gz\n", HEX256),
+                Some(format!("sha256:{}", HEX256)),
+            ),
+            (
+                format!("{}  dist/tool.tar.gz\n", HEX256),
+                Some(format!("sha256:{}", HEX256)),
+            ),
+            (
+                format!("{}  other.zip\n{}  tool.tar.gz\n", "00".repeat(32), HEX256),
+                Some(format!("sha256:{}", HEX256)),
+            ),
+            (
+                format!("{}  tool.tar.gz", hex512),
+                Some(format!("sha512:{}", hex512)),
+            ),
+            (
+                format!("SHA256 (tool.tar.gz) = {}", HEX256),
+                Some(format!("sha256:{}", HEX256)),
+            ),
+            (format!("SHA256 (other.zip) = {}", HEX256), None),
+            (format!("SHA512 (tool.tar.gz) = {}", HEX256), None),
+            (format!("{}\n", HEX256), Some(format!("sha256:{}", HEX256))),
+            (format!("{}  other.zip\n", HEX256), None),
+            (format!("{}\n{}\n", HEX256, HEX256), None),
+            ("deadbeef  tool.tar.gz".to_string(), None),
+            (String::new(), None),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(find(&content, "tool.tar.gz"), expected, "{}", content);
+        }
+    }
+
+    #[test]
+    fn checksums_of_asset() {
+        let cases = [
+            ("tool.tar.gz.sha256", true),
+            ("tool.tar.gz.SHA512", true),
+            ("tool.tar.gz.sha256sum", true),
+            ("SHA256SUMS", true),
+            ("sha512sums.txt", true),
+            ("checksums.txt", true),
+            ("tool_1.0.0_checksums.txt", true),
+            ("tool.tar.gz.sig", false),
+            ("tool.tar.gz", false),
+            ("other.zip.sha256", false),
+            ("tool.zip", false),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(is_checksums_of(name, "tool.tar.gz"), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn verify_streamed_bytes() {
+        // sha256 of `test`.
+        let digest = format!("sha256:{}", HEX256);
+        let cases = [
+            (digest.as_str(), &[&b"te"[..], &b"st"[..]][..], true),
+            (digest.as_str(), &[&b"tesT"[..]][..], false),
+            (digest.as_str(), &[][..], false),
+        ];
+        for (expected, chunks, ok) in cases {
+            let mut verifier = Verifier::new(expected).unwrap();
+            for chunk in chunks {
+                verifier.update(chunk);
+            }
+            assert_eq!(verifier.finish().is_ok(), ok, "{:?}", chunks);
+        }
+        assert!(Verifier::new("md5:00").is_err());
+    }
+}
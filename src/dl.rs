@@ -2,23 +2,64 @@ use futures_lite::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
 use tokio::io::AsyncWriteExt;
 
+use crate::checksum;
+
 pub struct Downloader {
     dir: std::path::PathBuf,
+    /// Digests the download must match, and what they are.
+    expected: Vec<(String, String)>,
 }
 
 impl Downloader {
     pub fn new(dir: impl Into<std::path::PathBuf>) -> Downloader {
         let dir = dir.into();
         std::fs::create_dir_all(&dir).ok();
-        Downloader { dir }
+        Downloader {
+            dir,
+            expected: Vec::new(),
+        }
+    }
+
+    /// Checks the download against `digest`, as `<algorithm>:<hex>`, while streaming it,
+    /// removing the file if it does not match. `what` tells where the digest came from.
+    pub fn expect(mut self, digest: impl Into<String>, what: impl Into<String>) -> Downloader {
+        self.expected.push((digest.into(), what.into()));
+        self
     }
 
     pub async fn download(
         &self,
         url: impl AsRef<str>,
         name: impl AsRef<str>,
+    ) -> anyhow::Result<()> {
+        self.fetch(url, name, None).await
+    }
+
+    /// Downloads a release asset through the GitHub API `url` of it, authorized by `token`.
+    ///
+    /// The API redirects to a storage host, reqwest drops the `Authorization` header on
+    /// redirects to another host, so the token never leaks to it.
+    pub async fn download_asset(
+        &self,
+        url: impl AsRef<str>,
+        name: impl AsRef<str>,
+        token: &str,
+    ) -> anyhow::Result<()> {
+        self.fetch(url, name, Some(token)).await
+    }
+
+    async fn fetch(
+        &self,
+        url: impl AsRef<str>,
+        name: impl AsRef<str>,
+        token: Option<&str>,
     ) -> anyhow::Result<()> {
         let path = self.dir.join(name.as_ref());
+        let mut verifiers = self
+            .expected
+            .iter()
+            .map(|(digest, what)| Ok((checksum::Verifier::new(digest)?, what)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
         let mut f = tokio::fs::File::create(&path).await?;
 
         let attach = |err: reqwest::Error| -> anyhow::Error {
@@ -40,8 +81,13 @@ impl Downloader {
             .build()
             .map_err(attach)?;
 
-        let resp = cli
-            .get(url.as_ref())
+        let mut request = cli.get(url.as_ref());
+        if let Some(token) = token {
+            request = request
+                .header(reqwest::header::ACCEPT, "application/octet-stream")
+                .bearer_auth(token);
+        }
+        let resp = request
             .send()
             .await
             .map_err(attach)?
@@ -60,9 +106,20 @@ impl Downloader {
             let chunk = chunk?;
             let size = chunk.len();
             pb.inc(size as _);
+            for (verifier, _) in verifiers.iter_mut() {
+                verifier.update(&chunk);
+            }
             f.write_all(&chunk).await?;
         }
+        // tokio writes in the background, the file must be complete once this returns.
+        f.flush().await?;
         pb.finish_with_message("Downloaded");
+        for (verifier, what) in verifiers {
+            if let Err(err) = verifier.finish() {
+                tokio::fs::remove_file(&path).await.ok();
+                return Err(err.context(format!("`{}` does not match {}", url.as_ref(), what)));
+            }
+        }
         Ok(())
     }
 }
@@ -3,11 +3,19 @@ use std::str::FromStr;
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use crate::checksum;
 use crate::dl;
+use crate::httpcache::{self, HttpCache};
+use crate::ratelimit::{self, RateLimit};
 
 #[derive(Clone, Debug)]
 pub struct Github {
     c: surf::Client,
+    api: String,
+    token: Option<String>,
+    cache: HttpCache,
+    /// Requests reserved for the task this client works for.
+    reservation: Option<std::sync::Arc<ratelimit::Reservation>>,
 }
 
 #[allow(non_upper_case_globals)]
@@ -15,24 +23,214 @@ const GithubAccept: &str = "application/vnd.github.v3+json";
 
 impl Default for Github {
     fn default() -> Self {
-        Github::new("https://api.github.com/")
+        Github::new(&api_url("github.com"), None).expect("the API url of github.com is valid")
+    }
+}
+
+/// The default API base url of `host`, GitHub Enterprise Server serves it under `/api/v3/`.
+pub fn api_url(host: &str) -> String {
+    if host == "github.com" {
+        "https://api.github.com/".to_string()
+    } else {
+        format!("https://{}/api/v3/", host)
     }
 }
 
 impl Github {
-    pub fn new(api: &str) -> Github {
-        let api = surf::Url::parse(api).unwrap();
+    /// A client of the API at `api`, requests are authorized with `token` if given.
+    ///
+    /// Responses are cached under the cache dir and revalidated with `ETag`/`Last-Modified`.
+    pub fn new(api: &str, token: Option<String>) -> anyhow::Result<Github> {
+        let base = surf::Url::parse(api)
+            .map_err(|err| anyhow::anyhow!(err).context(format!("Invalid API url {}", api)))?;
         let mime = surf::http::Mime::from_str(GithubAccept).unwrap();
         let mut accept = surf::http::content::Accept::new();
         accept.push(mime);
-        let c = surf::Config::default()
+        let mut config = surf::Config::default()
             .set_timeout(std::time::Duration::from_secs(10).into())
-            .set_base_url(api)
+            .set_base_url(base)
             .add_header(accept.name(), accept.value())
-            .unwrap()
-            .try_into()
             .unwrap();
-        Github { c }
+        if let Some(token) = &token {
+            config = config
+                .add_header(
+                    surf::http::headers::AUTHORIZATION,
+                    format!("Bearer {}", token),
+                )
+                .map_err(|err| anyhow::anyhow!(err).context("Invalid token"))?;
+        }
+        let c = config
+            .try_into()
+            .map_err(|err| anyhow::anyhow!("{}", err).context("Can not create the API client"))?;
+        Ok(Github {
+            c,
+            api: api.to_string(),
+            token,
+            cache: HttpCache::new(crate::project_dirs().cache_dir().join("http")),
+            reservation: None,
+        })
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// The API base url.
+    pub fn api(&self) -> &str {
+        &self.api
+    }
+
+    /// The last known quota of this API.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        ratelimit::get(&self.api)
+    }
+
+    /// Asks the API for the quota, which does not count against it.
+    pub async fn fetch_rate_limit(&self) -> surf::Result<RateLimit> {
+        #[derive(Deserialize)]
+        struct Resources {
+            core: RateLimit,
+        }
+        #[derive(Deserialize)]
+        struct Limits {
+            resources: Resources,
+        }
+        let mut response = is_ok(self.c.get("rate_limit").await?).await?;
+        let limits: Limits = response.body_json().await?;
+        ratelimit::set(&self.api, limits.resources.core);
+        Ok(limits.resources.core)
+    }
+
+    /// A client which may send `requests` no other task takes from the quota, bulk operations
+    /// use this to stay under it. Requests beyond those take what nobody reserved.
+    pub fn reserve(&self, requests: u64) -> surf::Result<Github> {
+        let reservation =
+            ratelimit::reserve(&self.api, requests).map_err(|limit| self.limited(&limit))?;
+        Ok(Github {
+            reservation: Some(std::sync::Arc::new(reservation)),
+            ..self.clone()
+        })
+    }
+
+    fn limited(&self, limit: &RateLimit) -> surf::Error {
+        let hint = if self.token.is_none() {
+            ", set a token (`GITHUB_TOKEN` or `token` in up.toml) to raise the limit"
+        } else {
+            ""
+        };
+        surf::Error::from_str(
+            surf::StatusCode::Forbidden,
+            format!(
+                "rate limited by {} until {}{}",
+                &self.api,
+                limit.reset_at().format("%H:%M"),
+                hint
+            ),
+        )
+    }
+
+    /// Sends `request`, a secondary rate limit is waited out and retried once if it is short.
+    async fn send(&self, request: surf::Request) -> surf::Result<surf::Response> {
+        let mut retried = false;
+        loop {
+            // Held until the quota reported with the response is set.
+            let _in_flight = ratelimit::take(&self.api, self.reservation.as_deref())
+                .map_err(|limit| self.limited(&limit))?;
+            let response = self.c.send(request.clone()).await?;
+            let limit = RateLimit::from_headers(&response);
+            if let Some(limit) = limit {
+                ratelimit::set(&self.api, limit);
+            }
+            let status = response.status();
+            if status == surf::StatusCode::Forbidden || status == surf::StatusCode::TooManyRequests
+            {
+                match ratelimit::retry_after(&response) {
+                    Some(wait) if !retried && wait <= ratelimit::MAX_RETRY_AFTER => {
+                        println!(
+                            "Hit a secondary rate limit of {}, retry in {}s",
+                            &self.api,
+                            wait.as_secs()
+                        );
+                        tokio::time::sleep(wait).await;
+                        retried = true;
+                        continue;
+                    }
+                    _ => {}
+                }
+                if let Some(limit) = limit.filter(|limit| limit.remaining == 0) {
+                    return Err(self.limited(&limit));
+                }
+            }
+            if status == surf::StatusCode::NotModified {
+                return Ok(response);
+            }
+            return is_ok(response).await;
+        }
+    }
+
+    /// Sends `request` conditionally on the cached response of its url, a `304 Not Modified`
+    /// is answered from the cache, which does not count against the quota.
+    async fn get_json<T: serde::de::DeserializeOwned>(
+        &self,
+        request: surf::RequestBuilder,
+    ) -> surf::Result<T> {
+        Ok(self.get_page(request).await?.0)
+    }
+
+    /// Same as `get_json`, with the url of the next page from the `Link` header.
+    async fn get_page<T: serde::de::DeserializeOwned>(
+        &self,
+        request: surf::RequestBuilder,
+    ) -> surf::Result<(T, Option<String>)> {
+        let mut request = request.build();
+        let url = request.url().to_string();
+        let cached = self.cache.get(&url).await;
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.etag {
+                request.insert_header(surf::http::headers::IF_NONE_MATCH, etag.as_str());
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request.insert_header(
+                    surf::http::headers::IF_MODIFIED_SINCE,
+                    last_modified.as_str(),
+                );
+            }
+        }
+        let mut response = self.send(request).await?;
+        let entry = match cached {
+            Some(cached) if response.status() == surf::StatusCode::NotModified => cached,
+            _ => {
+                let header = |name| response.header(name).map(|v| v.as_str().to_string());
+                let link = response.header("link").map(|v| v.as_str().to_string());
+                let mut entry = httpcache::Entry {
+                    url,
+                    etag: header(surf::http::headers::ETAG),
+                    last_modified: header(surf::http::headers::LAST_MODIFIED),
+                    next: link.as_deref().and_then(next_link),
+                    body: String::new(),
+                };
+                entry.body = response.body_string().await?;
+                if entry.etag.is_some() || entry.last_modified.is_some() {
+                    self.cache.put(&entry).await;
+                }
+                entry
+            }
+        };
+        Ok((serde_json::from_str(&entry.body)?, entry.next))
+    }
+
+    /// Items of all pages, starting with `request`.
+    async fn get_all<T: serde::de::DeserializeOwned>(
+        &self,
+        request: surf::RequestBuilder,
+    ) -> surf::Result<Vec<T>> {
+        let (mut items, mut next): (Vec<T>, _) = self.get_page(request).await?;
+        while let Some(url) = next {
+            let (page, following): (Vec<T>, _) = self.get_page(self.c.get(url)).await?;
+            items.extend(page);
+            next = following;
+        }
+        Ok(items)
     }
 
     pub async fn get(&self, uri: &str) -> surf::Result<surf::Response> {
@@ -60,8 +258,33 @@ impl GhRepo {
         GhRelease { repo: self.clone() }
     }
 
+    // Relative, so it keeps the path of the base url, e.g. `/api/v3/` of GitHub Enterprise.
     fn path(&self) -> String {
-        format!("/repos/{}/{}", self.owner, self.name)
+        format!("repos/{}/{}", self.owner, self.name)
+    }
+
+    pub fn full_name(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    /// Sigstore bundles of the artifact attestations of `digest`, as `<algorithm>:<hex>`.
+    ///
+    /// Empty if nothing is attested for it.
+    pub async fn attestations(&self, digest: &str) -> surf::Result<Vec<serde_json::Value>> {
+        #[derive(Deserialize)]
+        struct Attestation {
+            bundle: serde_json::Value,
+        }
+        #[derive(Deserialize)]
+        struct Attestations {
+            attestations: Vec<Attestation>,
+        }
+        let path = format!("{}/attestations/{}", self.path(), digest);
+        match self.gh.get_json::<Attestations>(self.gh.c.get(path)).await {
+            Ok(found) => Ok(found.attestations.into_iter().map(|a| a.bundle).collect()),
+            Err(err) if err.status() == surf::StatusCode::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
     }
 }
 
@@ -76,11 +299,14 @@ pub struct Pagination {
     per_page: usize,
 }
 
+/// The most items the API returns per page.
+pub const MAX_PER_PAGE: usize = 100;
+
 impl Default for Pagination {
     fn default() -> Self {
         Pagination {
             page: 1,
-            per_page: 20,
+            per_page: MAX_PER_PAGE,
         }
     }
 }
@@ -121,99 +347,110 @@ pub fn pagination(page: usize, per_page: usize) -> Pagination {
     Pagination::new(page, per_page)
 }
 
+/// The `rel="next"` url of a `Link` header, like
+/// `<https://api.github.com/...?page=2>; rel="next", <https://api.github.com/...?page=5>; rel="last"`.
+fn next_link(link: &str) -> Option<String> {
+    link.split(',').find_map(|link| {
+        let mut parts = link.split(';');
+        let url = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+        parts
+            .any(|param| param.trim() == "rel=\"next\"")
+            .then(|| url.to_string())
+    })
+}
+
+/// Releases of a repo, newest first, fetched a page at a time on demand.
+#[derive(Clone, Debug)]
+pub struct ReleasePages {
+    releases: GhRelease,
+    next: Option<String>,
+    started: bool,
+}
+
+impl ReleasePages {
+    /// Whether there may be more releases to fetch.
+    pub fn has_more(&self) -> bool {
+        !self.started || self.next.is_some()
+    }
+
+    /// The next page, `None` after the last one.
+    pub async fn next_page(&mut self) -> surf::Result<Option<Vec<Release>>> {
+        let gh = &self.releases.repo.gh;
+        let request = match (self.started, self.next.take()) {
+            (false, _) => {
+                gh.c.get([self.releases.repo.path(), self.releases.path()].join("/"))
+                    .query(&Pagination::default())?
+            }
+            (true, Some(url)) => gh.c.get(url),
+            (true, None) => return Ok(None),
+        };
+        self.started = true;
+        let (mut releases, next): (Vec<Release>, _) = gh.get_page(request).await?;
+        self.next = next;
+        releases.iter_mut().for_each(|release| {
+            release.gh.replace(self.releases.clone());
+        });
+        Ok(Some(releases))
+    }
+}
+
 impl GhRelease {
     fn path(&self) -> String {
         "releases".into()
     }
 
-    pub async fn releases(
-        &self,
-        page: Option<Pagination>,
-        since: &mut Option<DateTime<Local>>,
-    ) -> surf::Result<Vec<Release>> {
-        let path = vec![self.repo.path(), self.path()].join("/");
+    /// A single page of the releases, newest first.
+    pub async fn releases(&self, page: Option<Pagination>) -> surf::Result<Vec<Release>> {
+        let path = [self.repo.path(), self.path()].join("/");
         let mut request = self.repo.gh.c.get(path);
         if let Some(page) = page {
             request = request.query(&page)?;
         };
-        if let Some(since) = since {
-            let since = surf::http::conditional::IfModifiedSince::new(since.clone().into());
-            request = request.header(since.name(), since.value());
+        let mut releases: Vec<Release> = self.repo.gh.get_json(request).await?;
+        releases.iter_mut().for_each(|release| {
+            release.gh.replace(self.clone());
+        });
+        Ok(releases)
+    }
+
+    /// All releases, following the pages on demand.
+    pub fn pages(&self) -> ReleasePages {
+        ReleasePages {
+            releases: self.clone(),
+            next: None,
+            started: false,
         }
-        let request = request.build();
-        let response = surf::client().send(request).await?;
-        let mut response = is_ok(response).await?;
-        if let Some(last) = surf::http::conditional::LastModified::from_headers(&response)? {
-            println!("last modified: {:?}", last.modified());
-            since.replace(last.modified().into());
-        };
-        response
-            .body_json()
-            .await
-            .and_then(|mut releases: Vec<Release>| {
-                releases.iter_mut().for_each(|release| {
-                    release.gh.replace(self.clone());
-                });
-                Ok(releases)
-            })
     }
 
-    pub async fn latest(&self, since: &mut Option<DateTime<Local>>) -> surf::Result<Release> {
+    pub async fn latest(&self) -> surf::Result<Release> {
         let repos = self.repo.path();
         let releases = self.path();
         let mut seg: Vec<&str> = Vec::with_capacity(5);
         seg.push(&repos);
         seg.push(&releases);
         seg.push("latest");
-        let mut request = self.repo.gh.c.get(seg.join("/"));
-        if let Some(since) = since {
-            let since = surf::http::conditional::IfModifiedSince::new(since.clone().into());
-            request = request.header(since.name(), since.value());
-        }
-        let mut response = is_ok(request.await?).await?;
-        if let Some(last) = surf::http::conditional::LastModified::from_headers(&response)? {
-            since.replace(last.modified().into());
-        };
-        response.body_json().await.map(|mut release: Release| {
-            release.gh.replace(self.clone());
-            release
-        })
+        let request = self.repo.gh.c.get(seg.join("/"));
+        let mut release: Release = self.repo.gh.get_json(request).await?;
+        release.gh.replace(self.clone());
+        Ok(release)
     }
 
-    pub async fn oftag(
-        &self,
-        tagname: &str,
-        since: &mut Option<DateTime<Local>>,
-    ) -> surf::Result<Release> {
-        let mut seg = Vec::with_capacity(5);
+    pub async fn oftag(&self, tagname: &str) -> surf::Result<Release> {
+        let mut seg: Vec<&str> = Vec::with_capacity(5);
         let repos = self.repo.path();
         let releases = self.path();
-        seg.push("");
         seg.push(&repos);
         seg.push(&releases);
         seg.push("tags");
         seg.push(tagname);
-        let mut request = self.repo.gh.c.get(seg.join("/"));
-        if let Some(since) = since {
-            let since = surf::http::conditional::IfModifiedSince::new(since.clone().into());
-            request = request.header(since.name(), since.value());
-        }
-        let mut response = is_ok(request.await?).await?;
-        if let Some(last) = surf::http::conditional::LastModified::from_headers(&response)? {
-            since.replace(last.modified().into());
-        };
-        response.body_json().await.map(|mut release: Release| {
-            release.gh.replace(self.clone());
-            release
-        })
+        let request = self.repo.gh.c.get(seg.join("/"));
+        let mut release: Release = self.repo.gh.get_json(request).await?;
+        release.gh.replace(self.clone());
+        Ok(release)
     }
 
-    async fn assets(
-        &self,
-        release: usize,
-        page: Option<Pagination>,
-        since: &mut Option<DateTime<Local>>,
-    ) -> surf::Result<Vec<Asset>> {
+    /// All assets of `release`, following the pages.
+    async fn assets(&self, release: usize) -> surf::Result<Vec<Asset>> {
         let mut seg: Vec<&str> = Vec::with_capacity(5);
         let repos = self.repo.path();
         let releases = self.path();
@@ -222,19 +459,17 @@ impl GhRelease {
         seg.push(&releases);
         seg.push(&release);
         seg.push("assets");
-        let mut request = self.repo.gh.c.get(seg.join("/"));
-        if let Some(page) = page {
-            request = request.query(&page)?;
-        }
-        if let Some(since) = since {
-            let since = surf::http::conditional::IfModifiedSince::new(since.clone().into());
-            request = request.header(since.name(), since.value());
-        }
-        let mut response = is_ok(request.await?).await?;
-        if let Some(last) = surf::http::conditional::LastModified::from_headers(&response)? {
-            since.replace(last.modified().into());
-        };
-        response.body_json().await
+        let request = self
+            .repo
+            .gh
+            .c
+            .get(seg.join("/"))
+            .query(&Pagination::default())?;
+        let mut assets: Vec<Asset> = self.repo.gh.get_all(request).await?;
+        assets.iter_mut().for_each(|asset| {
+            asset.gh.replace(self.repo.gh.clone());
+        });
+        Ok(assets)
     }
 }
 
@@ -257,21 +492,23 @@ pub struct Release {
     desc: Option<String>,
     #[serde(rename(deserialize = "tag_name"))]
     pub tagname: Option<String>,
-    prerelease: bool,
-    pub published_at: DateTime<Local>,
+    pub prerelease: bool,
+    #[serde(default)]
+    pub draft: bool,
+    /// `None` for drafts.
+    pub published_at: Option<DateTime<Local>>,
     #[serde(skip)]
     gh: Option<GhRelease>,
 }
 
 impl Release {
-    pub async fn assets(&self, since: &mut Option<DateTime<Local>>) -> surf::Result<Vec<Asset>> {
+    pub async fn assets(&self) -> surf::Result<Vec<Asset>> {
         let gh = self.gh.clone().unwrap();
-        gh.assets(self.id, Pagination::default().into(), since)
-            .await
+        gh.assets(self.id).await
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Asset {
     pub name: String,
     pub url: surf::Url,
@@ -279,46 +516,112 @@ pub struct Asset {
     pub label: Option<String>,
     pub updated_at: DateTime<Local>,
     pub browser_download_url: surf::Url,
+    /// Digest of the asset as `<algorithm>:<hex>`, reported by newer GitHub versions.
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(skip)]
+    gh: Option<Github>,
 }
 
 impl Asset {
+    /// An asset downloaded from `url` as is, without asking the API for it, named by the
+    /// last segment of the url.
+    pub fn of_url(url: &str, updated_at: DateTime<Local>) -> anyhow::Result<Asset> {
+        let url = surf::Url::parse(url)
+            .map_err(|err| anyhow::anyhow!(err).context(format!("Invalid url {}", url)))?;
+        let name = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("asset")
+            .to_string();
+        Ok(Asset {
+            name,
+            url: url.clone(),
+            size: 0,
+            label: None,
+            updated_at,
+            browser_download_url: url,
+            digest: None,
+            gh: None,
+        })
+    }
+
     // Download the release
     pub async fn download(&self) -> anyhow::Result<std::path::PathBuf> {
+        self.download_pinned(None).await
+    }
+
+    /// Downloads the asset, checking it against `pinned`, the digest recorded when it was
+    /// installed before, and the one reported by GitHub while streaming it.
+    pub async fn download_pinned(
+        &self,
+        pinned: Option<&str>,
+    ) -> anyhow::Result<std::path::PathBuf> {
         println!(
             "Downloading {} from {}",
             self.name, &self.browser_download_url
         );
         let tempdir = std::env::temp_dir().join("up");
-        // let path = tempdir.join(&self.name);
-        let downloader = dl::Downloader::new(&tempdir);
-        downloader
-            .download(&self.browser_download_url, &self.name)
-            .await?;
-
-        Ok(tempdir.join(&self.name))
+        // Apps upgraded at once may download assets of the same name.
+        static DOWNLOADS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let n = DOWNLOADS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let file = format!("{}-{}-{}", std::process::id(), n, &self.name);
+        let mut downloader = dl::Downloader::new(&tempdir);
+        if let Some(pinned) = pinned {
+            downloader = downloader.expect(
+                pinned,
+                "the digest pinned when it was installed, it was replaced upstream since",
+            );
+        }
+        if let Some(digest) = self
+            .digest
+            .as_deref()
+            .filter(|digest| checksum::is_supported(digest))
+        {
+            downloader = downloader.expect(digest, "the digest reported by GitHub");
+        }
+        match self.gh.as_ref().and_then(|gh| gh.token()) {
+            // Assets of private repositories are only reachable through the API.
+            Some(token) => downloader.download_asset(&self.url, &file, token).await?,
+            None => {
+                downloader
+                    .download(&self.browser_download_url, &file)
+                    .await?
+            }
+        }
+        Ok(tempdir.join(&file))
     }
 }
 
 impl Release {
+    /// The tag of the release, which names its version, the title may be anything.
+    pub fn tag(&self) -> &str {
+        self.tagname.as_deref().unwrap_or(&self.name)
+    }
+
     pub fn url(&self) -> &surf::Url {
         &self.url
     }
 
     pub fn desc(&self) -> Option<&str> {
-        self.desc.as_ref().map(|s| s.as_str())
+        self.desc.as_deref()
     }
 }
 
-impl ToString for Release {
-    fn to_string(&self) -> String {
-        format!("{}\t({})", self.name, self.published_at.format("%F %T"))
+impl std::fmt::Display for Release {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.published_at {
+            Some(published_at) => write!(f, "{}\t({})", self.tag(), published_at.format("%F %T")),
+            None => write!(f, "{}\t(draft)", self.tag()),
+        }
     }
 }
 
-impl ToString for Asset {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Asset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let label = if let Some(label) = &self.label {
-            if label.len() > 0 {
+            if !label.is_empty() {
                 format!("({})", label)
             } else {
                 String::new()
@@ -326,7 +629,8 @@ impl ToString for Asset {
         } else {
             String::new()
         };
-        format!(
+        write!(
+            f,
             "{}\t{}\t\t{}",
             bytesize::ByteSize::b(self.size as _).to_string_as(true),
             self.name,
@@ -494,3 +798,31 @@ impl RepoInfo {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_link_of_header() {
+        let cases = [
+            (
+                r#"<https://api.github.com/repos/o/r/releases?page=2>; rel="next", <https://api.github.com/repos/o/r/releases?page=5>; rel="last""#,
+                Some("https://api.github.com/repos/o/r/releases?page=2"),
+            ),
+            (
+                r#"<https://api.github.com/repos/o/r/releases?page=1>; rel="prev", <https://api.github.com/repos/o/r/releases?page=3>; rel="next""#,
+                Some("https://api.github.com/repos/o/r/releases?page=3"),
+            ),
+            (
+                r#"<https://api.github.com/repos/o/r/releases?page=4>; rel="prev", <https://api.github.com/repos/o/r/releases?page=1>; rel="first""#,
+                None,
+            ),
+            ("", None),
+            ("garbage", None),
+        ];
+        for (link, expected) in cases {
+            assert_eq!(next_link(link).as_deref(), expected, "{}", link);
+        }
+    }
+}
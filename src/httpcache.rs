@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A cached API response and the validators to revalidate it with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Entry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The `rel="next"` url of the `Link` header, for paginated responses.
+    #[serde(default)]
+    pub next: Option<String>,
+    pub body: String,
+}
+
+/// Responses of conditional requests on disk, one file per url, so a `304 Not Modified`
+/// in a later run can be answered from it.
+#[derive(Clone, Debug)]
+pub struct HttpCache {
+    dir: PathBuf,
+}
+
+impl HttpCache {
+    pub fn new(dir: impl Into<PathBuf>) -> HttpCache {
+        HttpCache { dir: dir.into() }
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        let key = format!("{:x}", Sha256::digest(url.as_bytes()));
+        self.dir.join(key).with_extension("json")
+    }
+
+    /// The cached response of `url`, a missing or unreadable one is a miss.
+    pub async fn get(&self, url: &str) -> Option<Entry> {
+        let content = tokio::fs::read(self.path(url)).await.ok()?;
+        serde_json::from_slice::<Entry>(&content)
+            .ok()
+            .filter(|entry| entry.url == url)
+    }
+
+    /// Stores `entry`, written aside and renamed so concurrent readers never see a partial
+    /// one. Failures are ignored, the cache only saves requests.
+    pub async fn put(&self, entry: &Entry) {
+        let path = self.path(&entry.url);
+        let content = match serde_json::to_vec(entry) {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+        let staging = path.with_extension(format!("{}.tmp", std::process::id()));
+        tokio::fs::create_dir_all(&self.dir).await.ok();
+        if tokio::fs::write(&staging, content).await.is_ok()
+            && tokio::fs::rename(&staging, &path).await.is_err()
+        {
+            tokio::fs::remove_file(&staging).await.ok();
+        }
+    }
+}
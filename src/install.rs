@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+use tokio::io::AsyncWriteExt;
+
+/// `.{name}.{suffix}` next to `target`, on the same filesystem so it can be renamed over it.
+fn sibling(target: &Path, suffix: &str) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+/// A new version of `target` being written aside of it.
+///
+/// Nothing touches `target` until `commit`, a staging file which is dropped uncommitted is
+/// removed, so a failure at any stage leaves the installed version intact.
+pub struct Staging {
+    target: PathBuf,
+    path: PathBuf,
+    file: Option<tokio::fs::File>,
+    committed: bool,
+}
+
+impl Staging {
+    pub async fn new(target: impl Into<PathBuf>) -> anyhow::Result<Staging> {
+        let target = target.into();
+        let path = sibling(&target, "up-staging");
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .mode(0o755)
+            .open(&path)
+            .await
+            .map_err(|err| {
+                anyhow::anyhow!(err).context(format!("Can not create {}", path.display()))
+            })?;
+        Ok(Staging {
+            target,
+            path,
+            file: Some(file),
+            committed: false,
+        })
+    }
+
+    pub fn file(&mut self) -> &mut tokio::fs::File {
+        self.file.as_mut().unwrap()
+    }
+
+    /// Swaps the staging file in for `target`, the sha256 of the installed file.
+    ///
+    /// The staged file must match `expected`, the sha256 recorded when the same asset was
+    /// installed before, or `target` is left alone. The previous `target` is kept as a
+    /// backup until the rename succeeded, and is restored if it did not.
+    pub async fn commit(mut self, expected: Option<&str>) -> anyhow::Result<String> {
+        let mut file = self.file.take().unwrap();
+        file.flush().await?;
+        file.sync_all().await?;
+        drop(file);
+        let staged = crate::checksum::sha256(&self.path)?;
+        if let Some(expected) = expected {
+            anyhow::ensure!(
+                staged.eq_ignore_ascii_case(expected),
+                "{} unpacked to sha256 {}, not {} as installed before, kept the installed one",
+                self.target.display(),
+                staged,
+                expected
+            );
+        }
+
+        let backup = sibling(&self.target, "up-backup");
+        let has_backup = match tokio::fs::symlink_metadata(&self.target).await {
+            Ok(_) => {
+                tokio::fs::remove_file(&backup).await.ok();
+                // A hard link keeps `target` in place until the rename replaces it.
+                if tokio::fs::hard_link(&self.target, &backup).await.is_err() {
+                    tokio::fs::copy(&self.target, &backup).await?;
+                }
+                true
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        if let Err(err) = tokio::fs::rename(&self.path, &self.target).await {
+            if has_backup && tokio::fs::symlink_metadata(&self.target).await.is_err() {
+                tokio::fs::rename(&backup, &self.target).await.ok();
+                sync_dir(&self.target).await;
+            }
+            return Err(
+                anyhow::anyhow!(err).context(format!("Can not replace {}", self.target.display()))
+            );
+        }
+        self.committed = true;
+        sync_dir(&self.target).await;
+        if has_backup {
+            tokio::fs::remove_file(&backup).await.ok();
+        }
+        Ok(staged)
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.file.take();
+            std::fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+/// Makes a rename in the directory of `path` durable, best effort.
+async fn sync_dir(path: &Path) {
+    if let Some(dir) = path.parent() {
+        if let Ok(dir) = tokio::fs::File::open(dir).await {
+            dir.sync_all().await.ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn commit_checks_the_staged_file() {
+        let dir = std::env::temp_dir().join(format!("up-install-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let target = dir.join("tool");
+        tokio::fs::write(&target, b"old").await.unwrap();
+
+        let cases = [(Some("0".repeat(64)), false, "old"), (None, true, "new")];
+        for (expected, ok, content) in cases {
+            let mut staging = Staging::new(&target).await.unwrap();
+            staging.file().write_all(b"new").await.unwrap();
+            let committed = staging.commit(expected.as_deref()).await;
+            assert_eq!(committed.is_ok(), ok, "{:?}", expected);
+            assert_eq!(tokio::fs::read_to_string(&target).await.unwrap(), content);
+            assert!(!sibling(&target, "up-staging").exists());
+            assert!(!sibling(&target, "up-backup").exists());
+        }
+        tokio::fs::remove_dir_all(&dir).await.ok();
+    }
+}
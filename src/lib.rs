@@ -1,12 +1,30 @@
-use std::str::FromStr;
-
+pub mod activate;
 pub mod archive;
+pub mod attestation;
+pub mod auth;
+pub mod checksum;
 pub mod dl;
 pub mod ghapi;
+pub mod httpcache;
+pub mod install;
+pub mod matcher;
 pub mod opt;
+pub mod project;
+pub mod ratelimit;
+pub mod report;
+pub mod run;
+pub mod shim;
+pub mod signature;
+pub mod store;
 pub mod sysinfo;
+pub mod template;
 pub mod ui;
 
+/// Where `up` keeps its config, data and cache.
+pub fn project_dirs() -> directories::ProjectDirs {
+    directories::ProjectDirs::from("me", "songww", "up").unwrap()
+}
+
 pub trait Anyhow<T> {
     fn anyhow(self) -> anyhow::Result<T>;
 }
@@ -16,35 +34,3 @@ impl<T> Anyhow<T> for surf::Result<T> {
         self.map_err(|err| anyhow::anyhow!(err))
     }
 }
-
-pub fn is_compatible(app: &str, version: &str, asset: &str) -> bool {
-    let triple = take_triple(app, version, asset);
-    let triple = target::Triple::from_str(triple).expect(triple);
-    let host = target::HOST;
-    host.vendor == triple.vendor
-        && host.architecture == triple.architecture
-        && host.operating_system == triple.operating_system
-}
-
-fn take_triple<'a>(app: &str, version: &str, asset: &'a str) -> &'a str {
-    let mut name = asset;
-    if asset.starts_with(app) {
-        name = unsafe { name.get_unchecked(app.len()..) };
-    }
-    if name.starts_with("-") {
-        name = unsafe { name.get_unchecked(1..) };
-    }
-    if name.starts_with(version) {
-        name = unsafe { name.get_unchecked(version.len()..) };
-    }
-    if name.starts_with("v") && unsafe { name.get_unchecked(1..) }.starts_with(version) {
-        name = unsafe { name.get_unchecked(version.len() + 1..) };
-    }
-    if name.starts_with("-") {
-        name = unsafe { name.get_unchecked(1..) };
-    }
-    if let Some(pos) = name.find(|c| c == '.') {
-        name = unsafe { name.get_unchecked(..pos) };
-    }
-    name
-}
@@ -1,13 +1,29 @@
 use clap::Parser;
+use futures_util::StreamExt;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use up::{ghapi, opt, ui, Anyhow};
+use up::{
+    activate, attestation, checksum, ghapi, install, matcher, opt, project, report, run, shim,
+    signature, store, sysinfo, template, ui, Anyhow,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let cli = opt::Cli::parse();
+    let proj = up::project_dirs();
+    let locked_versions_path = proj.data_dir().join("versions.lock.toml");
+
+    // Invoked through the shim of an app, run its active version instead.
+    if let Some(name) = shim::invoked_as() {
+        let locked_versions = tokio::fs::read_to_string(&locked_versions_path)
+            .await
+            .map_err(|err| {
+                anyhow::anyhow!(err).context(format!("{} is not installed by up", &name))
+            })?;
+        let locked_versions: opt::AppVersions = toml::de::from_str(&locked_versions)?;
+        return Err(shim::exec(&name, &locked_versions));
+    }
 
-    let proj = directories::ProjectDirs::from("me", "songww", "up").unwrap();
+    let cli = opt::Cli::parse();
     let config_path = if let Some(config) = cli.config {
         config
     } else {
@@ -18,11 +34,14 @@ async fn main() -> anyhow::Result<()> {
     if !config_path.exists() {
         tokio::fs::create_dir(proj.config_dir()).await.ok();
         let src = toml::ser::to_string_pretty(&opt::Config {
+            token: None,
+            keep_versions: None,
+            hosts: indexmap::IndexMap::new(),
             apps: indexmap::IndexMap::new(),
         })?;
         tokio::fs::File::create(&config_path)
             .await?
-            .write(src.as_bytes())
+            .write_all(src.as_bytes())
             .await?;
     }
     tokio::fs::File::open(&config_path)
@@ -32,16 +51,15 @@ async fn main() -> anyhow::Result<()> {
     let mut config: opt::Config = toml::de::from_str(&configs)?;
 
     let mut locked_versions = String::new();
-    let locked_versions_path = proj.data_dir().join("versions.lock.toml");
     if !locked_versions_path.exists() {
         tokio::fs::create_dir(&proj.data_dir()).await.ok();
         let src = toml::ser::to_string_pretty(&opt::AppVersions {
             apps: indexmap::IndexMap::new(),
+            versions: indexmap::IndexMap::new(),
         })?;
-        println!("default locked versions: {}", &src);
         tokio::fs::File::create(&locked_versions_path)
             .await?
-            .write(src.as_bytes())
+            .write_all(src.as_bytes())
             .await?;
     }
     tokio::fs::File::open(&locked_versions_path)
@@ -54,20 +72,32 @@ async fn main() -> anyhow::Result<()> {
     //     &locked_versions
     // );
     let mut locked_versions: opt::AppVersions = toml::de::from_str(&locked_versions)?;
+    if let Err(err) = shim::repoint(&locked_versions).await {
+        eprintln!("Can not re-point the shims of the apps: {:#}", err);
+    }
+    let store = store::Store::default();
 
     match cli.command {
         opt::Commands::Install {
             name,
             repo,
+            host,
+            api_url,
             unpack,
             binname,
             latest,
             version,
             asset_name,
+            url,
             allow_prerelease,
             after_downloaded,
             r#type,
+            libc,
+            signature,
         } => {
+            let signature = signature
+                .config()
+                .or_else(|| config.apps.get(&name).and_then(|cfg| cfg.signature.clone()));
             // anyhow::ensure!(
             //     locked_versions.apps.contains_key(&name),
             //     anyhow::anyhow!("{} already installed.", &name)
@@ -78,18 +108,50 @@ async fn main() -> anyhow::Result<()> {
                 latest,
                 allow_prerelease,
                 after_downloaded,
-                asset_name,
+                asset_name: asset_name.or_else(|| {
+                    config
+                        .apps
+                        .get(&name)
+                        .and_then(|cfg| cfg.asset_name.clone())
+                }),
+                url,
                 version,
                 r#type,
                 unpack,
                 binname,
-                app_version: None,
+                libc: libc.or_else(|| config.apps.get(&name).and_then(|cfg| cfg.libc)),
+                host,
+                api_url,
+                signature,
+                attestation: config
+                    .apps
+                    .get(&name)
+                    .and_then(|cfg| cfg.attestation.clone()),
+                rules: config
+                    .apps
+                    .get(&name)
+                    .map(|cfg| cfg.rules.clone())
+                    .unwrap_or_default(),
+                // Reinstalls are checked against the recorded digest.
+                app_version: locked_versions.apps.get(&name).cloned(),
             };
 
-            up(&mut opts).await?;
-            locked_versions
-                .apps
-                .insert(name.to_string(), opts.app_version.clone().unwrap());
+            let gh = opts.github(&config)?;
+            let outcome = up(&gh, &mut opts, true).await?;
+            let installed = opts.app_version.clone().unwrap();
+            match outcome {
+                Outcome::Installed => {
+                    keep(
+                        &mut locked_versions,
+                        &store,
+                        config.keep_versions(),
+                        &installed,
+                    )
+                    .await
+                }
+                Outcome::UpToDate => println!("{} is already the newest version.", &name),
+            }
+            locked_versions.apps.insert(name.to_string(), installed);
             config.apps.insert(name, opts.into());
         }
         opt::Commands::Update {
@@ -104,16 +166,16 @@ async fn main() -> anyhow::Result<()> {
                 .apps
                 .get_mut(&name)
                 .ok_or_else(|| anyhow::anyhow!("{} not installed yet.", &name))?;
-            let cfg: opt::AppConfig = app.clone().try_into().unwrap();
-            if repo.is_none() {
-                repo.replace(cfg.repo);
+            let cfg = app.clone();
+            if let Some(repo) = &repo {
+                app.repo = repo.clone();
             } else {
-                app.repo = repo.as_ref().unwrap().clone().into();
+                repo.replace(cfg.repo);
             }
-            if asset_name.is_none() {
-                asset_name.replace(cfg.asset_name);
+            if let Some(asset_name) = &asset_name {
+                app.asset_name = Some(asset_name.clone());
             } else {
-                app.asset_name = asset_name.as_ref().unwrap().clone().into();
+                asset_name = cfg.asset_name;
             }
             if allow_prerelease || cfg.allow_prerelease {
                 allow_prerelease = true;
@@ -132,26 +194,510 @@ async fn main() -> anyhow::Result<()> {
                 latest: version.is_none(),
                 version,
                 asset_name,
+                url: cfg.url,
                 allow_prerelease,
                 after_downloaded,
                 r#type: cfg.r#type,
                 unpack: cfg.unpack,
                 binname: cfg.binname,
+                libc: cfg.libc,
+                host: cfg.host,
+                api_url: cfg.api_url,
+                signature: cfg.signature,
+                attestation: cfg.attestation,
+                rules: cfg.rules,
                 app_version: app_version.into(),
             };
-            up(&mut opts).await?;
-            locked_versions
-                .apps
-                .insert(name.to_string(), opts.app_version.clone().unwrap());
+            let gh = opts.github(&config)?;
+            let outcome = up(&gh, &mut opts, true).await?;
+            let installed = opts.app_version.clone().unwrap();
+            if let Outcome::Installed = outcome {
+                keep(
+                    &mut locked_versions,
+                    &store,
+                    config.keep_versions(),
+                    &installed,
+                )
+                .await;
+            }
+            locked_versions.apps.insert(name.to_string(), installed);
             config.apps.insert(name, opts.into());
         }
 
-        opt::Commands::Upgrade { allow_prerelease } => {
-            //
-            for (name, appcfg) in config.apps.iter() {
-                // up(&mut opt).await?;
+        opt::Commands::Upgrade {
+            allow_prerelease,
+            jobs,
+        } => {
+            let tasks: Vec<_> = config
+                .apps
+                .values()
+                .map(|cfg| {
+                    let mut opts = opt::Options::from(cfg.clone());
+                    opts.allow_prerelease |= allow_prerelease;
+                    opts.app_version = locked_versions.apps.get(&cfg.name).cloned();
+                    (opts.github(&config), opts)
+                })
+                .collect();
+            fetch_rate_limits(tasks.iter().filter_map(|(gh, _)| gh.as_ref().ok())).await;
+            let mut failed = Vec::new();
+            // Apps which need the user to choose are upgraded one by one after the others,
+            // so no two prompts compete for the terminal.
+            let mut pending = tasks;
+            let mut interactive = false;
+            while !pending.is_empty() {
+                let jobs = if interactive { 1 } else { jobs.max(1) };
+                let mut upgrades = futures_util::stream::iter(std::mem::take(&mut pending))
+                    .map(move |(gh, mut opts)| async move {
+                        let gh = match gh {
+                            Ok(gh) => gh,
+                            Err(err) => return (None, opts, Err(err)),
+                        };
+                        let outcome = match gh.reserve(UPGRADE_REQUESTS) {
+                            Ok(reserved) => up(&reserved, &mut opts, interactive).await,
+                            Err(err) => Err(anyhow::anyhow!(err).context("skipped")),
+                        };
+                        (Some(gh), opts, outcome)
+                    })
+                    .buffer_unordered(jobs);
+
+                while let Some((gh, opts, outcome)) = upgrades.next().await {
+                    if matches!(&outcome, Err(err) if err.is::<NeedsChoice>()) {
+                        if let Some(gh) = gh {
+                            println!("{}: needs a choice, asking after the others", &opts.name);
+                            pending.push((Ok(gh), opts));
+                            continue;
+                        }
+                    }
+                    let previous = locked_versions
+                        .apps
+                        .get(&opts.name)
+                        .map(|v| v.version.clone());
+                    match outcome {
+                        Ok(Outcome::Installed) => {
+                            let installed = opts.app_version.as_ref().unwrap();
+                            match previous {
+                                Some(previous) => println!(
+                                    "{}: updated {} -> {}",
+                                    &opts.name, previous, &installed.version
+                                ),
+                                None => {
+                                    println!("{}: installed {}", &opts.name, &installed.version)
+                                }
+                            }
+                            keep(
+                                &mut locked_versions,
+                                &store,
+                                config.keep_versions(),
+                                installed,
+                            )
+                            .await;
+                        }
+                        Ok(Outcome::UpToDate) => {
+                            println!(
+                                "{}: already current ({})",
+                                &opts.name,
+                                previous.unwrap_or_default()
+                            );
+                        }
+                        Err(err) => {
+                            println!("{}: failed: {:#}", &opts.name, err);
+                            failed.push(opts.name);
+                            continue;
+                        }
+                    }
+                    // Persist after every app, a partial run should not be lost.
+                    let app_version = opts.app_version.clone().unwrap();
+                    locked_versions.apps.insert(opts.name.clone(), app_version);
+                    config.apps.insert(opts.name.clone(), opts.into());
+                    save(&locked_versions_path, &locked_versions).await?;
+                }
+                interactive = true;
+            }
+            save(&config_path, &config).await?;
+            anyhow::ensure!(
+                failed.is_empty(),
+                "{} app(s) failed to upgrade: {}",
+                failed.len(),
+                failed.join(", ")
+            );
+        }
+
+        opt::Commands::Uninstall {
+            name,
+            force,
+            keep_config,
+            dry_run,
+        } => {
+            anyhow::ensure!(
+                config.apps.contains_key(&name) || locked_versions.apps.contains_key(&name),
+                "{} not installed yet.",
+                &name
+            );
+            if let Some(app_version) = locked_versions.apps.get(&name) {
+                uninstall(app_version, force, dry_run)?;
+            }
+            let mut shims: Vec<_> = locked_versions
+                .apps
+                .get(&name)
+                .map(|app_version| {
+                    let linked = app_version
+                        .files
+                        .iter()
+                        .filter_map(|file| shim::path_of(file));
+                    app_version
+                        .shims
+                        .iter()
+                        .cloned()
+                        .chain(linked)
+                        .filter(|path| shim::is_shim(path, &app_version.shims))
+                        .collect()
+                })
+                .unwrap_or_default();
+            shims.dedup();
+            let versions = locked_versions
+                .versions
+                .get(&name)
+                .map(Vec::len)
+                .unwrap_or(0);
+            if dry_run {
+                for path in shims.iter() {
+                    println!("would remove {}", path.display());
+                }
+                if versions > 0 {
+                    println!("would remove {} installed version(s)", versions);
+                }
+                if !keep_config {
+                    println!("would forget {}", &name);
+                }
+                return Ok(());
+            }
+            for path in shims.iter() {
+                tokio::fs::remove_file(path).await?;
+                println!("removed {}", path.display());
+            }
+            store.remove_all(&name).await;
+            locked_versions.versions.shift_remove(&name);
+            locked_versions.apps.shift_remove(&name);
+            if !keep_config {
+                config.apps.shift_remove(&name);
+            }
+        }
+
+        opt::Commands::Rollback { name } => {
+            let current = locked_versions
+                .apps
+                .get(&name)
+                .ok_or_else(|| anyhow::anyhow!("{} not installed yet.", &name))?;
+            let previous = locked_versions.previous(&name).cloned().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "no version of {} before {} is installed, see `up list --all-versions`",
+                    &name,
+                    &current.version
+                )
+            })?;
+            println!(
+                "{}: rolled back {} -> {}",
+                &name, &current.version, &previous.version
+            );
+            activate(&mut config, &mut locked_versions, &store, previous).await?;
+        }
+
+        opt::Commands::Use { name, version } => {
+            anyhow::ensure!(
+                locked_versions.apps.contains_key(&name),
+                "{} not installed yet.",
+                &name
+            );
+            let target = locked_versions
+                .version(&name, &version)
+                .cloned()
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "{} {} is not installed, see `up list --all-versions`",
+                        &name,
+                        &version
+                    )
+                })?;
+            println!("{}: using {}", &name, &target.version);
+            activate(&mut config, &mut locked_versions, &store, target).await?;
+        }
+
+        opt::Commands::Sync => {
+            let dir = std::env::current_dir()?;
+            let (path, manifest) = project::find(&dir)?.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "no {} in {} or its parents",
+                    project::FILE_NAME,
+                    dir.display()
+                )
+            })?;
+            let mut failed = Vec::new();
+            for (name, pinned) in manifest.apps.iter() {
+                let installed = locked_versions.version(name, &pinned.version);
+                if let Some(installed) = installed.filter(|v| store.verify(v).is_ok()) {
+                    println!("{}: {} already installed", name, &installed.version);
+                    locked_versions.pin(name, &pinned.version);
+                    save(&locked_versions_path, &locked_versions).await?;
+                    continue;
+                }
+                let synced = async {
+                    let mut opts = pinned.options(name, config.apps.get(name))?;
+                    // Reinstalls are checked against the recorded digest.
+                    opts.app_version = installed
+                        .or_else(|| locked_versions.apps.get(name))
+                        .cloned();
+                    let gh = opts.github(&config)?;
+                    up(&gh, &mut opts, true).await?;
+                    anyhow::Ok(opts)
+                }
+                .await;
+                let opts = match synced {
+                    Ok(opts) => opts,
+                    Err(err) => {
+                        println!("{}: failed: {:#}", name, err);
+                        failed.push(name.as_str());
+                        continue;
+                    }
+                };
+                let installed = opt::AppVersion {
+                    pinned: true,
+                    ..opts.app_version.clone().unwrap()
+                };
+                println!("{}: installed {}", name, &installed.version);
+                // Other projects may pin the other versions, sync never drops one.
+                locked_versions.add_version(installed.clone(), usize::MAX);
+                if !locked_versions.apps.contains_key(name) {
+                    locked_versions.apps.insert(name.clone(), installed);
+                    config.apps.insert(name.clone(), opts.into());
+                }
+                save(&locked_versions_path, &locked_versions).await?;
             }
-            unimplemented!()
+            save(&config_path, &config).await?;
+            anyhow::ensure!(
+                failed.is_empty(),
+                "{} app(s) of {} failed to sync: {}",
+                failed.len(),
+                path.display(),
+                failed.join(", ")
+            );
+            return Ok(());
+        }
+
+        opt::Commands::Run {
+            target,
+            asset_name,
+            unpack,
+            binname,
+            args,
+        } => {
+            let (repo, version) = match target.split_once('@') {
+                Some((repo, version)) => (repo.to_string(), Some(version.to_string())),
+                None => (target, None),
+            };
+            let opts = opt::Options {
+                latest: version.is_none(),
+                version,
+                asset_name,
+                unpack,
+                binname,
+                ..opt::Options::of_repo(repo)
+            };
+            let redirect = run::StdoutToStderr::new()?;
+            let executable = fetch_run(&config, &opts, &run::Cache::default()).await?;
+            drop(redirect);
+            return Err(run::exec(&executable, &args));
+        }
+
+        opt::Commands::Explain {
+            repo,
+            version,
+            format,
+        } => {
+            // An app of up.toml is explained with its settings.
+            let opts = match config.apps.get(&repo) {
+                Some(cfg) => opt::Options::from(cfg.clone()),
+                None => opt::Options::of_repo(repo),
+            };
+            let gh = opts.github(&config)?;
+            let releases = opts.repo()?.github(gh).releases();
+            let release = match &version {
+                Some(version) => releases.oftag(version).await.anyhow()?,
+                None => newest_release(&releases, opts.allow_prerelease).await?,
+            };
+            let assets = release.assets().await.anyhow()?;
+            let system = sysinfo::System::new()?;
+            let asset_name = asset_name(&opts, &release, Some(&system))?;
+            let explanation = report::Explanation::new(
+                &opts.repo,
+                release.tag(),
+                assets.iter().map(|asset| asset.name.as_str()),
+                asset_name.as_deref(),
+                &system,
+                &opts.preferences(Some(release.tag())),
+            )?;
+            return report::explain(&explanation, format);
+        }
+
+        opt::Commands::Activate { shell, env } => {
+            if !env {
+                print!("{}", activate::hook(shell)?);
+            } else if let Ok(dir) = std::env::current_dir() {
+                if let Some(commands) = activate::env(shell, &dir, &locked_versions) {
+                    print!("{}", commands);
+                }
+            }
+            return Ok(());
+        }
+
+        opt::Commands::Outdated { jobs } => {
+            let apps: Vec<_> = config
+                .apps
+                .values()
+                .map(|cfg| (cfg.clone(), locked_versions.apps.get(&cfg.name).cloned()))
+                .collect();
+            let config = &config;
+            let clients: Vec<_> = config
+                .apps
+                .values()
+                .filter_map(|cfg| opt::Options::from(cfg.clone()).github(config).ok())
+                .collect();
+            fetch_rate_limits(clients.iter()).await;
+            let mut checks = futures_util::stream::iter(apps)
+                .map(|(cfg, installed)| async move {
+                    let outcome = outdated(config, &cfg, installed.as_ref()).await;
+                    (cfg, installed, outcome)
+                })
+                .buffered(jobs.max(1));
+
+            let mut updates = Vec::new();
+            let mut failed = Vec::new();
+            while let Some((cfg, installed, outcome)) = checks.next().await {
+                match outcome {
+                    Ok(Some(release)) => updates.push((cfg.name, installed, release)),
+                    Ok(None) => {}
+                    Err(err) => {
+                        println!("{}: failed: {:#}", &cfg.name, err);
+                        failed.push(cfg.name);
+                    }
+                }
+            }
+
+            if updates.is_empty() && failed.is_empty() {
+                println!("All apps are up to date.");
+            }
+            for (name, installed, release) in updates.iter() {
+                println!(
+                    "{}: {} -> {}{}",
+                    name,
+                    installed
+                        .as_ref()
+                        .map(|v| v.version.as_str())
+                        .unwrap_or("-"),
+                    &release,
+                    if release.prerelease {
+                        ", prerelease"
+                    } else {
+                        ""
+                    },
+                );
+            }
+            anyhow::ensure!(
+                failed.is_empty(),
+                "{} app(s) failed to check: {}",
+                failed.len(),
+                failed.join(", ")
+            );
+            if !updates.is_empty() {
+                std::process::exit(OUTDATED);
+            }
+            return Ok(());
+        }
+
+        opt::Commands::RateLimit => {
+            let mut clients = vec![config.github(opt::GITHUB, None)?];
+            clients.extend(
+                config
+                    .apps
+                    .values()
+                    .filter_map(|cfg| opt::Options::from(cfg.clone()).github(&config).ok()),
+            );
+            let mut seen = std::collections::HashSet::new();
+            for gh in clients
+                .iter()
+                .filter(|gh| seen.insert(gh.api().to_string()))
+            {
+                let auth = if gh.token().is_some() {
+                    "authorized"
+                } else {
+                    "anonymous"
+                };
+                match gh.fetch_rate_limit().await {
+                    Ok(limit) => println!(
+                        "{}\t{}/{} requests left, resets at {} ({})",
+                        gh.api(),
+                        limit.remaining,
+                        limit.limit,
+                        limit.reset_at().format("%H:%M"),
+                        auth
+                    ),
+                    Err(err) => println!("{}\tfailed: {}", gh.api(), err),
+                }
+            }
+            return Ok(());
+        }
+
+        opt::Commands::List {
+            format,
+            all_versions: true,
+        } => {
+            let versions: Vec<_> = locked_versions
+                .versions
+                .values()
+                .flatten()
+                .map(|installed| {
+                    let current = locked_versions.apps.get(&installed.name);
+                    Ok(report::Version::new(
+                        installed,
+                        current
+                            .map(|v| v.version == installed.version)
+                            .unwrap_or(false),
+                        store.version_dir(&installed.name, &installed.version)?,
+                    ))
+                })
+                .collect::<anyhow::Result<_>>()?;
+            return report::versions(&versions, format);
+        }
+
+        opt::Commands::List { format, .. } => {
+            let mut names: Vec<_> = config.apps.keys().collect();
+            names.extend(
+                locked_versions
+                    .apps
+                    .keys()
+                    .filter(|name| !config.apps.contains_key(*name)),
+            );
+            let summaries: Vec<_> = names
+                .into_iter()
+                .map(|name| {
+                    report::Summary::new(
+                        name,
+                        config.apps.get(name),
+                        locked_versions.apps.get(name),
+                    )
+                })
+                .collect();
+            return report::list(&summaries, format);
+        }
+
+        opt::Commands::Info { name, format } => {
+            let cfg = config.apps.get(&name);
+            let installed = locked_versions.apps.get(&name);
+            anyhow::ensure!(
+                cfg.is_some() || installed.is_some(),
+                "{} not installed yet.",
+                &name
+            );
+            return report::info(&report::Info::new(&name, cfg, installed), format);
         }
 
         _ => {
@@ -200,18 +746,484 @@ async fn main() -> anyhow::Result<()> {
     //         None => show_error!("Cannot find a release for your OS and Arch\n Use --list flag to list all available options"),
     //     }
     // }
-    tokio::fs::write(&config_path, toml::ser::to_string_pretty(&config)?).await?;
-    println!("locked versions: {:?}", &locked_versions);
-    tokio::fs::write(
-        &locked_versions_path,
-        toml::ser::to_string_pretty(&locked_versions)?,
-    )
-    .await?;
+    save(&config_path, &config).await?;
+    save(&locked_versions_path, &locked_versions).await?;
+    Ok(())
+}
+
+async fn save<T: serde::Serialize>(path: &std::path::Path, value: &T) -> anyhow::Result<()> {
+    tokio::fs::write(path, toml::ser::to_string_pretty(value)?).await?;
+    Ok(())
+}
+
+/// Removes the files recorded in `app_version`.
+///
+/// Nothing is removed if any of them was modified after installation, unless `force`.
+fn uninstall(app_version: &opt::AppVersion, force: bool, dry_run: bool) -> anyhow::Result<()> {
+    let mut files = Vec::with_capacity(app_version.files.len());
+    let mut modified = Vec::new();
+    for file in app_version.files.iter() {
+        if !file.exists() {
+            println!("{} already removed", file.display());
+            continue;
+        }
+        // Installed before checksums were recorded, can not tell, taken as unchanged.
+        if let Some(sum) = app_version.checksums.get(file) {
+            if &checksum::sha256(file)? != sum {
+                modified.push(file);
+            }
+        }
+        files.push(file);
+    }
+    if !force && !modified.is_empty() && !dry_run {
+        let modified: Vec<_> = modified
+            .iter()
+            .map(|file| file.display().to_string())
+            .collect();
+        anyhow::bail!(
+            "refusing to remove files modified since installation, use `--force` to remove anyway: {}",
+            modified.join(", ")
+        );
+    }
+    for file in files {
+        if dry_run {
+            let note = if modified.contains(&file) {
+                " (modified since installation)"
+            } else {
+                ""
+            };
+            println!("would remove {}{}", file.display(), note);
+        } else {
+            std::fs::remove_file(file)?;
+            println!("removed {}", file.display());
+        }
+    }
+    Ok(())
+}
+
+/// Requests reserved for an app: the release, its assets and maybe the list. Further pages
+/// take from the quota nobody reserved.
+const UPGRADE_REQUESTS: u64 = 3;
+
+/// Asks every API for its quota, so bulk operations know how many requests they may send.
+async fn fetch_rate_limits<'a>(clients: impl Iterator<Item = &'a ghapi::Github>) {
+    let mut seen = std::collections::HashSet::new();
+    for gh in clients {
+        if seen.insert(gh.api()) {
+            // Unreachable hosts fail again for each app, with a better context.
+            gh.fetch_rate_limit().await.ok();
+        }
+    }
+}
+
+/// Exit status of `up outdated` when any update is available.
+const OUTDATED: i32 = 10;
+
+/// The newest release of the app if it differs from the installed one.
+async fn outdated(
+    config: &opt::Config,
+    cfg: &opt::AppConfig,
+    installed: Option<&opt::AppVersion>,
+) -> anyhow::Result<Option<ghapi::Release>> {
+    let opts = opt::Options::from(cfg.clone());
+    let gh = opts
+        .github(config)?
+        .reserve(1)
+        .map_err(|err| anyhow::anyhow!(err).context("skipped"))?;
+    let releases = opts.repo()?.github(gh).releases();
+    let newest = newest_release(&releases, cfg.allow_prerelease).await?;
+    match installed {
+        Some(installed) if newest.tag() == installed.version => Ok(None),
+        _ => Ok(Some(newest)),
+    }
+}
+
+/// The digest recorded when `asset` of `release` was installed before, reinstalling it, its
+/// bytes must not have changed since, trust on first use.
+fn pinned_digest(
+    opts: &opt::Options,
+    release: &ghapi::Release,
+    asset: &ghapi::Asset,
+) -> Option<String> {
+    opts.app_version.as_ref().and_then(|installed| {
+        (installed.version == release.tag() && installed.asset.as_ref() == Some(&asset.name))
+            .then(|| installed.digest.clone())
+            .flatten()
+    })
+}
+
+/// Verifies the downloaded `asset`, the digest to record for it, its own if none was
+/// published.
+///
+/// `Asset::download_pinned` already checked it against the pinned digest.
+async fn verify_asset(
+    opts: &opt::Options,
+    repo: &ghapi::GhRepo,
+    release: &ghapi::Release,
+    assets: &[ghapi::Asset],
+    asset: &ghapi::Asset,
+    asset_path: &std::path::Path,
+) -> anyhow::Result<(String, Option<opt::Attestation>)> {
+    let digest = verify_checksums(assets, asset, asset_path).await?;
+    let pinned = pinned_digest(opts, release, asset);
+    if let Some(cfg) = &opts.signature {
+        verify_signature(cfg, assets, asset, asset_path).await?;
+    }
+    let attestation = match &opts.attestation {
+        Some(cfg) => {
+            let attestation = attestation::verify(cfg, repo, asset_path)
+                .await
+                .map_err(|err| {
+                    err.context(format!("Can not verify the attestation of {}", &asset.name))
+                })?;
+            match &attestation.reason {
+                None => println!(
+                    "Verified the attestation of {}, built by {}",
+                    &asset.name,
+                    attestation.workflow.as_deref().unwrap_or("-")
+                ),
+                Some(reason) => println!("Unverified attestation of {}: {}", &asset.name, reason),
+            }
+            Some(attestation)
+        }
+        None => None,
+    };
+    // `Asset::download` already verified the one reported by GitHub. Without any, the bytes
+    // downloaded now are trusted on first use.
+    let digest = match digest.or(pinned).or_else(|| asset.digest.clone()) {
+        Some(digest) => digest,
+        None => checksum::digest(asset_path, checksum::Algorithm::Sha256)?,
+    };
+    Ok((digest, attestation))
+}
+
+/// Verifies the downloaded `asset` by its signature asset.
+async fn verify_signature(
+    cfg: &opt::SignatureConfig,
+    assets: &[ghapi::Asset],
+    asset: &ghapi::Asset,
+    asset_path: &std::path::Path,
+) -> anyhow::Result<()> {
+    let names = signature::asset_names(cfg, &asset.name);
+    let sig = assets
+        .iter()
+        .find(|sig| names.contains(&sig.name))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "no signature of {} found, expected one of {}",
+                &asset.name,
+                names.join(", ")
+            )
+        })?;
+    let path = sig.download().await?;
+    let verified = signature::verify(cfg, asset_path, &path).await;
+    tokio::fs::remove_file(&path).await.ok();
+    verified
+        .map_err(|err| err.context(format!("Can not verify {} by {}", &asset.name, &sig.name)))?;
+    println!("Verified the signature of {} by {}", &asset.name, &sig.name);
+    Ok(())
+}
+
+/// Verifies the downloaded `asset` against the checksums published next to it.
+///
+/// The verified digest, `None` if the release publishes no checksum of it.
+async fn verify_checksums(
+    assets: &[ghapi::Asset],
+    asset: &ghapi::Asset,
+    asset_path: &std::path::Path,
+) -> anyhow::Result<Option<String>> {
+    let sums = assets.iter().filter(|sums| {
+        sums.name != asset.name && checksum::is_checksums_of(&sums.name, &asset.name)
+    });
+    for sums in sums {
+        let path = sums.download().await?;
+        let content = tokio::fs::read_to_string(&path).await;
+        tokio::fs::remove_file(&path).await.ok();
+        let expected = match checksum::find(&content?, &asset.name) {
+            Some(expected) => expected,
+            None => continue,
+        };
+        checksum::verify(asset_path, &expected).map_err(|err| {
+            err.context(format!("Can not verify {} by {}", &asset.name, &sums.name))
+        })?;
+        println!("Verified {} by {}", &asset.name, &sums.name);
+        return Ok(Some(expected));
+    }
+    Ok(None)
+}
+
+/// Writes the executable in `asset_path` to `target`, unpacking it if it is an archive.
+async fn extract(
+    opts: &opt::Options,
+    asset_path: &std::path::Path,
+    target: &mut tokio::fs::File,
+) -> anyhow::Result<()> {
+    let ar_files = compressed::list_archive_files(std::fs::File::open(asset_path)?)?;
+    let mut source = tokio::fs::File::open(asset_path).await?;
+
+    if !ar_files.is_empty() {
+        if ar_files.len() == 1 && &ar_files[0] == "data" {
+            compressed::tokio_support::uncompress_archive_file(&mut source, target, "data").await?;
+        } else if let Some(unpack) = &opts.unpack.as_ref() {
+            compressed::tokio_support::uncompress_archive_file(&mut source, target, unpack).await?;
+        } else if ar_files.len() == 1 {
+            compressed::tokio_support::uncompress_archive_file(&mut source, target, &ar_files[0])
+                .await?;
+        } else {
+            anyhow::bail!("too many files in the asset archive, not support yet.");
+        }
+    } else {
+        tokio::io::copy(&mut source, target).await?;
+    }
+    Ok(())
+}
+
+/// Records the newly `installed` version, removing the oldest ones beyond `n` from the store.
+async fn keep(
+    locked_versions: &mut opt::AppVersions,
+    store: &store::Store,
+    n: usize,
+    installed: &opt::AppVersion,
+) {
+    for dropped in locked_versions.add_version(installed.clone(), n) {
+        store.remove(&dropped).await;
+    }
+}
+
+/// Makes the installed version `target` the one its shims run, after checking its files.
+async fn activate(
+    config: &mut opt::Config,
+    locked_versions: &mut opt::AppVersions,
+    store: &store::Store,
+    target: opt::AppVersion,
+) -> anyhow::Result<()> {
+    store.verify(&target)?;
+    let (owned, shims) = locked_versions
+        .apps
+        .get(&target.name)
+        .map(|active| (active.files.as_slice(), active.shims.as_slice()))
+        .unwrap_or_default();
+    let mut linked = Vec::with_capacity(target.files.len());
+    for file in target.files.iter() {
+        if let Some(path) = shim::path_of(file) {
+            shim::link(&path, owned, shims).await?;
+            linked.push(path);
+        }
+    }
+    let target = opt::AppVersion {
+        shims: linked,
+        ..target
+    };
+    if let (Some(cfg), Some(asset)) = (config.apps.get_mut(&target.name), &target.asset) {
+        // A template names the asset of every version already.
+        if !cfg.asset_name.as_deref().is_some_and(template::is_template) {
+            cfg.asset_name = Some(asset.clone());
+        }
+    }
+    locked_versions.apps.insert(target.name.clone(), target);
     Ok(())
 }
 
-async fn up(opts: &mut opt::Options) -> anyhow::Result<()> {
-    let gh = ghapi::Github::default();
+/// `up` can not tell which release or asset to install, the user has to choose.
+#[derive(Debug)]
+struct NeedsChoice;
+
+impl std::fmt::Display for NeedsChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "needs a choice")
+    }
+}
+
+impl std::error::Error for NeedsChoice {}
+
+/// What `up` did for an app.
+enum Outcome {
+    /// A release was downloaded and installed.
+    Installed,
+    /// The installed version is already the newest eligible release.
+    UpToDate,
+}
+
+/// The newest release of the repo, prereleases are included if `allow_prerelease`.
+///
+/// Unchanged releases are answered from the http cache, so checking again is cheap.
+async fn newest_release(
+    releases: &ghapi::GhRelease,
+    allow_prerelease: bool,
+) -> anyhow::Result<ghapi::Release> {
+    if allow_prerelease {
+        // `releases/latest` never returns a prerelease, the newest one of the list may be.
+        releases
+            .releases(Some(ghapi::Pagination::of_per_page(10)))
+            .await
+            .anyhow()?
+            .into_iter()
+            .find(|release| !release.draft)
+            .ok_or_else(|| anyhow::anyhow!("no release published yet."))
+    } else {
+        releases.latest().await.anyhow()
+    }
+}
+
+/// The `asset_name` of `opts`, rendered for `release` on `system` if it is a template.
+fn asset_name(
+    opts: &opt::Options,
+    release: &ghapi::Release,
+    system: Option<&sysinfo::System>,
+) -> anyhow::Result<Option<String>> {
+    match opts.asset_name.as_deref() {
+        Some(name) if template::is_template(name) => {
+            let vars = template_vars(opts, release, system);
+            Ok(Some(template::render(name, &vars)?))
+        }
+        name => Ok(name.map(String::from)),
+    }
+}
+
+/// Variables of the templates of `opts` for `release` on `system`.
+fn template_vars(
+    opts: &opt::Options,
+    release: &ghapi::Release,
+    system: Option<&sysinfo::System>,
+) -> template::Vars {
+    template::Vars::new(release.tag(), system, &opts.preferences(None))
+}
+
+/// The assets of `release` and the one of them to install, as `choose_asset` finds it, or
+/// only the one at the `url` of `opts`, without asking the API for the assets.
+async fn find_asset(
+    opts: &opt::Options,
+    release: &ghapi::Release,
+    interactive: bool,
+) -> anyhow::Result<(Vec<ghapi::Asset>, ghapi::Asset, Option<opt::AssetRule>)> {
+    if let Some(url) = &opts.url {
+        let system = sysinfo::System::new().ok();
+        let url = template::render(url, &template_vars(opts, release, system.as_ref()))?;
+        let updated_at = release.published_at.unwrap_or_else(chrono::Local::now);
+        let asset = ghapi::Asset::of_url(&url, updated_at)?;
+        println!("Select the asset: {}", &asset.name);
+        eprintln!(
+            "The assets of the release are not listed for a url, {} is not checked against \
+             the checksums published with it.",
+            &asset.name
+        );
+        return Ok((Vec::new(), asset, None));
+    }
+    let assets = release.assets().await.anyhow()?;
+    let (asset, rule) = choose_asset(opts, release, &assets, interactive).await?;
+    Ok((assets, asset, rule))
+}
+
+/// The asset of `release` to install, chosen interactively if several fit equally well,
+/// and a rule picking the equivalent one of later releases if so.
+async fn choose_asset(
+    opts: &opt::Options,
+    release: &ghapi::Release,
+    assets: &[ghapi::Asset],
+    interactive: bool,
+) -> anyhow::Result<(ghapi::Asset, Option<opt::AssetRule>)> {
+    let system = sysinfo::System::new()
+        .map_err(|err| eprintln!("{:#}, choose the asset yourself or set `rules`.", err))
+        .ok();
+    let asset_name = asset_name(opts, release, system.as_ref())?;
+    let options = matcher::select(
+        assets,
+        |asset| asset.name.as_str(),
+        asset_name.as_deref(),
+        system.as_ref(),
+        &opts.preferences(Some(release.tag())),
+    )?;
+    if let [asset] = &options[..] {
+        println!("Select the asset: {}", asset);
+        return Ok(((*asset).clone(), None));
+    }
+    if !interactive {
+        return Err(NeedsChoice.into());
+    }
+    let options: Vec<_> = if options.is_empty() {
+        assets.to_vec()
+    } else {
+        options.into_iter().cloned().collect()
+    };
+    let asset = ui::choose(&options, "Select the asset").await?.clone();
+    let rule = matcher::rule_for(&asset.name, release.tag());
+    Ok((asset, Some(rule)))
+}
+
+/// Downloads the executable of `opts` into the run cache, the cached one if `version` was
+/// run before.
+async fn fetch_run(
+    config: &opt::Config,
+    opts: &opt::Options,
+    cache: &run::Cache,
+) -> anyhow::Result<std::path::PathBuf> {
+    let binname = opts.binname.clone().unwrap_or_else(|| opts.name.clone());
+    let key = |version: &str| {
+        format!(
+            "{}@{} {} {} {}",
+            &opts.repo,
+            version,
+            &binname,
+            opts.asset_name.as_deref().unwrap_or_default(),
+            opts.unpack.as_deref().unwrap_or_default()
+        )
+    };
+    if let Some(version) = &opts.version {
+        if let Some(executable) = cache.get(&key(version)).await {
+            return Ok(executable);
+        }
+    }
+
+    let gh = opts.github(config)?;
+    let repo = opts.repo()?.github(gh);
+    let releases = repo.releases();
+    let release = match &opts.version {
+        Some(version) => releases.oftag(version).await.anyhow()?,
+        None => newest_release(&releases, false).await.map_err(|err| {
+            err.context(format!("Can not find the newest release of {}", &opts.repo))
+        })?,
+    };
+    let key = key(opts.version.as_deref().unwrap_or(release.tag()));
+    if let Some(executable) = cache.get(&key).await {
+        return Ok(executable);
+    }
+    let (assets, asset, _) = find_asset(opts, &release, true).await?;
+
+    let asset_path = asset
+        .download_pinned(pinned_digest(opts, &release, &asset).as_deref())
+        .await?;
+    let verified = verify_asset(opts, &repo, &release, &assets, &asset, &asset_path).await;
+    let sha256 = verified.and_then(|_| checksum::sha256(&asset_path));
+    let sha256 = match sha256 {
+        Ok(sha256) => sha256,
+        Err(err) => {
+            tokio::fs::remove_file(&asset_path).await.ok();
+            return Err(err);
+        }
+    };
+
+    let executable = cache.executable(&sha256, opts.unpack.as_deref(), &binname);
+    if !executable.exists() {
+        tokio::fs::create_dir_all(executable.parent().unwrap()).await?;
+        let mut staging = install::Staging::new(&executable).await?;
+        let extracted = extract(opts, &asset_path, staging.file()).await;
+        tokio::fs::remove_file(&asset_path).await.ok();
+        extracted?;
+        staging.commit(None).await?;
+    } else {
+        tokio::fs::remove_file(&asset_path).await.ok();
+    }
+    cache.put(&key, &executable).await?;
+    Ok(executable)
+}
+
+/// Installs the release of `opts`, asking the user to choose if `interactive`, failing with
+/// `NeedsChoice` if not.
+async fn up(
+    gh: &ghapi::Github,
+    opts: &mut opt::Options,
+    interactive: bool,
+) -> anyhow::Result<Outcome> {
     let repo = opts.repo()?.github(gh.clone());
 
     let basedir = directories::BaseDirs::new().unwrap();
@@ -221,103 +1233,121 @@ async fn up(opts: &mut opt::Options) -> anyhow::Result<()> {
     //     opts.version
     // }
 
-    let mut last_releases_since = opts.app_version.as_mut().and_then(|v| v.last_releases_at);
-    let mut last_latest_since = opts.app_version.as_mut().and_then(|v| v.last_latest_at);
     let releases = repo.releases();
+    let mut is_newest = false;
     let release = if let Some(version) = &opts.version {
-        releases.oftag(version, &mut None).await.anyhow()?
+        releases.oftag(version).await.anyhow()?
     } else if !opts.latest {
+        if !interactive {
+            return Err(NeedsChoice.into());
+        }
         // 列出releases, 从中选择一个
-        let options = releases
-            .releases(None, &mut last_releases_since)
-            .await
-            .anyhow()?;
-        ui::choose(&options, "Select the release").await?.clone()
+        let mut pages = releases.pages();
+        let mut options = Vec::new();
+        loop {
+            if let Some(page) = pages.next_page().await.anyhow()? {
+                options.extend(page);
+            }
+            if let Some(chosen) =
+                ui::choose_or_more(&options, "Select the release", pages.has_more()).await?
+            {
+                break chosen.clone();
+            }
+        }
     } else {
-        let latest_release = releases.latest(&mut last_latest_since).await.anyhow()?;
-        println!("Select the latest release: {}", latest_release.to_string());
-        latest_release
+        let newest = newest_release(&releases, opts.allow_prerelease)
+            .await
+            .map_err(|err| {
+                err.context(format!("Can not find the newest release of {}", &opts.repo))
+            })?;
+        println!("Select the newest release: {}", newest);
+        is_newest = true;
+        newest
     };
+    if is_newest
+        && matches!(&opts.app_version, Some(installed) if installed.version == release.tag())
+    {
+        return Ok(Outcome::UpToDate);
+    }
     if let Some(desc) = release.desc() {
-        println!("");
+        println!();
         println!("{}", desc);
-        println!("");
+        println!();
     }
-    let assets = release.assets(&mut None).await.anyhow()?;
-    let mut asset = None;
-    if let Some(asset_name) = &opts.asset_name {
-        assets
-            .iter()
-            .find(|asset| &asset.name == asset_name)
-            .and_then(|asset_| asset.replace(asset_));
+    let (assets, asset, rule) = find_asset(opts, &release, interactive).await?;
+    if let Some(rule) = rule {
+        // Picked by hand, pick the equivalent asset of later releases the same way.
+        println!(
+            "Saved a rule picking assets like it: include = {}",
+            rule.include.as_deref().unwrap_or_default()
+        );
+        // The one saved from an earlier choice picked something else, it is stale.
+        opts.rules.retain(|rule| !rule.auto);
+        opts.rules.push(rule);
     }
-    let asset = if asset.is_none() {
-        let options: Vec<_> = assets
-            .iter()
-            .filter(|asset| up::is_compatible(&opts.name, &release.name, &asset.name))
-            .map(|asset| asset.clone())
-            .collect();
-        if options.len() == 1 {
-            let asset = &options[0];
-            println!("Select the asset: {}", asset.to_string());
-            asset.clone()
-        } else if !options.is_empty() {
-            ui::choose(&options, "Select the asset").await?.clone()
-        } else {
-            ui::choose(&assets, "Select the asset").await?.clone()
-        }
-    } else {
-        asset.unwrap().clone()
-    };
-
-    opts.asset_name.replace(asset.name.clone());
 
-    let executable = executable_dir.join(opts.binname.as_ref().unwrap_or(&opts.name));
+    // A template names the asset of later releases too, a plain name only this one.
+    if opts
+        .asset_name
+        .as_deref()
+        .filter(|name| template::is_template(name))
+        .is_none()
+    {
+        opts.asset_name.replace(asset.name.clone());
+    }
 
-    let asset_path = asset.download().await?;
+    let binname = opts.binname.as_ref().unwrap_or(&opts.name);
+    let version_dir = store::Store::default().version_dir(&opts.name, release.tag())?;
+    let executable = version_dir.join(binname);
 
-    tokio::fs::remove_file(&executable).await?;
-    let mut target = tokio::fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .mode(0o755)
-        .open(&executable)
+    let asset_path = asset
+        .download_pinned(pinned_digest(opts, &release, &asset).as_deref())
         .await?;
-
-    let ar_files = compressed::list_archive_files(std::fs::File::open(&asset_path).unwrap())?;
-
-    if !ar_files.is_empty() {
-        let mut source = tokio::fs::File::open(&asset_path).await?;
-        if ar_files.len() == 1 && &ar_files[0] == "data" {
-            compressed::tokio_support::uncompress_archive_file(&mut source, &mut target, "data")
-                .await?;
-        } else if let Some(unpack) = &opts.unpack.as_ref() {
-            compressed::tokio_support::uncompress_archive_file(&mut source, &mut target, unpack)
-                .await?;
-        } else if ar_files.len() == 1 {
-            compressed::tokio_support::uncompress_archive_file(
-                &mut source,
-                &mut target,
-                &ar_files[0],
-            )
-            .await?;
-        } else {
-            anyhow::bail!("too many files in the asset archive, not support yet.");
+    let verified = verify_asset(opts, &repo, &release, &assets, &asset, &asset_path).await;
+    let (digest, attestation) = match verified {
+        Ok(verified) => verified,
+        Err(err) => {
+            tokio::fs::remove_file(&asset_path).await.ok();
+            return Err(err);
         }
-    } else {
-        tokio::fs::copy(&asset_path, &executable).await?;
-    }
+    };
 
-    tokio::fs::remove_file(&asset_path).await?;
+    tokio::fs::create_dir_all(&version_dir).await?;
+    let mut staging = install::Staging::new(&executable).await?;
+    let extracted = extract(opts, &asset_path, staging.file()).await;
+    tokio::fs::remove_file(&asset_path).await.ok();
+    extracted?;
+    // Reinstalling the same asset must unpack to the same file.
+    let expected = opts
+        .app_version
+        .as_ref()
+        .filter(|installed| {
+            installed.version == release.tag() && installed.asset.as_ref() == Some(&asset.name)
+        })
+        .and_then(|installed| installed.checksums.get(&executable));
+    let installed = staging.commit(expected.map(String::as_str)).await?;
+    let (owned, shims) = opts
+        .app_version
+        .as_ref()
+        .map(|installed| (installed.files.as_slice(), installed.shims.as_slice()))
+        .unwrap_or_default();
+    let shim = executable_dir.join(binname);
+    shim::link(&shim, owned, shims).await?;
 
+    let mut checksums = indexmap::IndexMap::new();
+    checksums.insert(executable.to_path_buf(), installed);
     opts.app_version.replace(opt::AppVersion {
         name: opts.name.to_string(),
-        version: release.name,
+        release_notes: release.desc().map(String::from),
+        version: release.tag().to_string(),
         files: vec![executable.to_path_buf()],
+        pinned: false,
+        shims: vec![shim],
+        checksums,
+        asset: Some(asset.name.clone()),
+        digest: Some(digest),
         updated_at: asset.updated_at,
-        last_latest_at: last_latest_since,
-        last_releases_at: last_releases_since,
+        attestation,
     });
-    Ok(())
+    Ok(Outcome::Installed)
 }
@@ -0,0 +1,704 @@
+use serde::Serialize;
+
+use crate::opt::AssetRule;
+use crate::sysinfo::{self, Libc, System, OS};
+
+/// What an asset is, by its name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    #[default]
+    App,
+    /// A distribution package, like `.deb` or `.msi`.
+    Package,
+    Source,
+    Debug,
+    Checksum,
+    Signature,
+    /// An SBOM, a provenance or some other document about the release.
+    Metadata,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::App => "app",
+            Kind::Package => "package",
+            Kind::Source => "source",
+            Kind::Debug => "debug",
+            Kind::Checksum => "checksum",
+            Kind::Signature => "signature",
+            Kind::Metadata => "metadata",
+        }
+    }
+}
+
+/// What the name of an asset tells about where it runs, by canonical names of
+/// `sysinfo`, `None` if the name does not tell.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Traits {
+    pub os: Option<&'static str>,
+    pub arch: Option<&'static str>,
+    pub libc: Option<&'static str>,
+    pub kind: Kind,
+}
+
+/// Runs on every arch of the OS, like the universal binaries of macOS.
+const UNIVERSAL: &str = "universal";
+
+/// Releases built on current CI images commonly need this glibc or a newer one, older
+/// hosts are better served by musl builds.
+const GLIBC_BASELINE: (u32, u32) = (2, 28);
+
+/// What the config of an app prefers, beyond fitting the host.
+#[derive(Clone, Debug, Default)]
+pub struct Preferences {
+    /// The libc wanted instead of the one fitting the host best.
+    pub libc: Option<Libc>,
+    pub rules: Vec<AssetRule>,
+    /// Words asset names carry which tell nothing about where they run, like the name of
+    /// the app and the version, stripped before looking at the rest.
+    pub ignore: Vec<String>,
+}
+
+/// Splits `name` into lowercase words, `tool_Linux_x86_64.tar.gz` into `tool`, `linux`,
+/// `x86`, `64`, `tar` and `gz`.
+fn tokenize(name: &str) -> Vec<String> {
+    name.to_ascii_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn canonical(aliases: &[&[&'static str]], token: &str) -> Option<&'static str> {
+    aliases
+        .iter()
+        .find(|names| names.contains(&token))
+        .map(|names| names[0])
+}
+
+/// `tokens` without the words of `ignore`, longest first, so the name of `arm-tool` does
+/// not tell its assets are for `arm`.
+fn strip(mut tokens: Vec<String>, ignore: &[String]) -> Vec<String> {
+    let mut ignore: Vec<_> = ignore
+        .iter()
+        .map(|word| tokenize(word))
+        .filter(|words| !words.is_empty())
+        .collect();
+    ignore.sort_by_key(|words| std::cmp::Reverse(words.len()));
+    for words in ignore {
+        let mut i = 0;
+        while i + words.len() <= tokens.len() {
+            if tokens[i..i + words.len()] == words[..] {
+                tokens.drain(i..i + words.len());
+            } else {
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// The OS of a package by its extension, which is no word of the OS in the middle of a name.
+fn os_of_extension(extension: &str) -> Option<&'static str> {
+    match extension {
+        "dmg" => Some(sysinfo::APPLE[0]),
+        "deb" | "rpm" | "appimage" => Some(sysinfo::LINUX[0]),
+        "exe" | "msi" => Some(sysinfo::WINDOWS[0]),
+        _ => None,
+    }
+}
+
+/// What the asset `name` is and where it runs, ignoring the words `ignore`.
+pub fn traits(name: &str, ignore: &[String]) -> Traits {
+    let tokens = strip(tokenize(name), ignore);
+    let mut traits = Traits::default();
+    let mut i = 0;
+    while i < tokens.len() {
+        // Aliases like `x86_64` span two words.
+        if let Some(next) = tokens.get(i + 1) {
+            let joined = format!("{}_{}", &tokens[i], next);
+            if let Some(arch) = canonical(sysinfo::ARCH_ALIASES, &joined) {
+                traits.arch.get_or_insert(arch);
+                i += 2;
+                continue;
+            }
+        }
+        let token = tokens[i].as_str();
+        if let Some(os) = canonical(sysinfo::OS_ALIASES, token) {
+            traits.os.get_or_insert(os);
+        } else if let Some(arch) = canonical(sysinfo::ARCH_ALIASES, token) {
+            traits.arch.get_or_insert(arch);
+        } else if matches!(token, "universal" | "universal2" | "fat") {
+            traits.arch.get_or_insert(UNIVERSAL);
+        }
+        if let Some(libc) = canonical(sysinfo::LIBC_ALIASES, token) {
+            traits.libc.get_or_insert(libc);
+        }
+        i += 1;
+    }
+    if traits.os.is_none() {
+        traits.os = tokens.last().and_then(|last| os_of_extension(last));
+    }
+    traits.kind = kind(&tokens);
+    traits
+}
+
+fn kind(tokens: &[String]) -> Kind {
+    let has = |words: &[&str]| tokens.iter().any(|token| words.contains(&token.as_str()));
+    let last = tokens.last().map(String::as_str).unwrap_or_default();
+    if has(&["checksums", "sha256sums", "sha512sums", "md5sums"])
+        || matches!(
+            last,
+            "sha256" | "sha512" | "sha256sum" | "sha512sum" | "sha1" | "md5"
+        )
+    {
+        Kind::Checksum
+    } else if has(&["sig", "asc", "minisig", "pem", "crt", "cert", "sigstore"]) {
+        Kind::Signature
+    } else if has(&["sbom", "spdx", "cdx", "intoto", "provenance"])
+        || matches!(last, "json" | "jsonl" | "txt" | "md" | "yaml" | "yml")
+    {
+        Kind::Metadata
+    } else if has(&["src", "source", "sources"]) {
+        Kind::Source
+    } else if has(&["debug", "dbg", "debuginfo", "pdb", "dsym", "symbols"]) {
+        Kind::Debug
+    } else if matches!(last, "deb" | "rpm" | "apk" | "msi" | "pkg" | "dmg") {
+        Kind::Package
+    } else {
+        Kind::App
+    }
+}
+
+/// How well an asset of `traits` fits `system`, higher is better, or why it can not
+/// run there.
+pub fn score(traits: &Traits, system: &System, prefs: &Preferences) -> Result<i32, String> {
+    let mut score = 0;
+    match traits.kind {
+        Kind::Checksum => return Err("a checksum file".to_string()),
+        Kind::Signature => return Err("a signature".to_string()),
+        Kind::Metadata => return Err("not an executable".to_string()),
+        Kind::Source => score -= 8,
+        Kind::Debug => score -= 8,
+        Kind::Package => score -= 2,
+        Kind::App => {}
+    }
+    match traits.os {
+        Some(os) if os == system.os().name() => score += 4,
+        Some(os) => return Err(format!("built for {}, not {}", os, system.os().name())),
+        None => {}
+    }
+    match traits.arch {
+        Some(arch) if arch == system.arch().name() => score += 4,
+        Some(arch) if system.arch().also_runs().contains(&arch) => score += 2,
+        Some(UNIVERSAL) => score += 2,
+        Some(arch) => return Err(format!("built for {}, not {}", arch, system.arch().name())),
+        None => {}
+    }
+    Ok(score + libc_score(traits.libc, system, prefs)?)
+}
+
+/// The wanted libc scores best, then assets which do not tell theirs, often static builds.
+fn libc_score(
+    libc: Option<&'static str>,
+    system: &System,
+    prefs: &Preferences,
+) -> Result<i32, String> {
+    if libc == Some("msvc") && !matches!(system.os(), OS::Windows) {
+        return Err("linked against msvc, a windows libc".to_string());
+    }
+    if !matches!(system.os(), OS::Linux) {
+        return Ok(0);
+    }
+    let wanted = wanted_libc(system, prefs);
+    match (libc, wanted) {
+        (Some(libc), Some(wanted)) if libc == wanted.name() => Ok(2),
+        (Some(libc), None) if libc == Libc::Musl.name() => Ok(1),
+        (Some(libc), _)
+            if libc == Libc::Gnu.name()
+                && prefs.libc.is_none()
+                && system.libc() == Some(Libc::Musl) =>
+        {
+            Err("linked against glibc, the host has musl".to_string())
+        }
+        (Some(_), _) => Ok(0),
+        (None, _) => Ok(1),
+    }
+}
+
+/// The libc assets for Linux `system` had better be linked against, `None` if it does not
+/// matter or can not be told.
+pub fn wanted_libc(system: &System, prefs: &Preferences) -> Option<Libc> {
+    if !matches!(system.os(), OS::Linux) {
+        return None;
+    }
+    prefs.libc.or_else(|| match system.libc()? {
+        Libc::Gnu => match system.glibc_version() {
+            Some(version) if version < GLIBC_BASELINE => Some(Libc::Musl),
+            _ => Some(Libc::Gnu),
+        },
+        Libc::Musl => Some(Libc::Musl),
+    })
+}
+
+/// An asset which can run on this host.
+#[derive(Clone, Debug)]
+pub struct Ranked<'a, T> {
+    pub asset: &'a T,
+    pub score: i32,
+}
+
+/// The `assets` which can run on `system`, the best first, by their `name`.
+pub fn rank<'a, T>(
+    assets: &'a [T],
+    name: impl Fn(&T) -> &str,
+    system: &System,
+    prefs: &Preferences,
+) -> Vec<Ranked<'a, T>> {
+    let mut ranked: Vec<_> = assets
+        .iter()
+        .filter_map(|asset| {
+            let score = score(&traits(name(asset), &prefs.ignore), system, prefs).ok()?;
+            Some(Ranked { asset, score })
+        })
+        .collect();
+    ranked.sort_by_key(|ranked| std::cmp::Reverse(ranked.score));
+    ranked
+}
+
+/// The best of `ranked`, more than one if their scores tie.
+pub fn best<'r, 'a, T>(ranked: &'r [Ranked<'a, T>]) -> &'r [Ranked<'a, T>] {
+    let ties = match ranked.first() {
+        Some(first) => ranked.iter().take_while(|r| r.score == first.score).count(),
+        None => 0,
+    };
+    &ranked[..ties]
+}
+
+/// A rule with its regexes compiled, to match the names of many assets.
+pub struct Picker<'r> {
+    rule: &'r AssetRule,
+    include: Option<regex::Regex>,
+    exclude: Option<regex::Regex>,
+    keywords: Vec<String>,
+}
+
+impl<'r> Picker<'r> {
+    pub fn new(rule: &'r AssetRule) -> anyhow::Result<Picker<'r>> {
+        let regex = |pattern: &String| {
+            regex::Regex::new(pattern).map_err(|err| {
+                anyhow::anyhow!(err).context(format!("Invalid asset rule {}", pattern))
+            })
+        };
+        Ok(Picker {
+            rule,
+            include: rule.include.as_ref().map(regex).transpose()?,
+            exclude: rule.exclude.as_ref().map(regex).transpose()?,
+            keywords: rule.keywords.iter().map(|k| k.to_lowercase()).collect(),
+        })
+    }
+
+    /// Whether the asset `name` is one the rule picks.
+    pub fn picks(&self, name: &str) -> bool {
+        if matches!(&self.include, Some(include) if !include.is_match(name)) {
+            return false;
+        }
+        if matches!(&self.exclude, Some(exclude) if exclude.is_match(name)) {
+            return false;
+        }
+        let name = name.to_lowercase();
+        self.keywords.iter().all(|keyword| name.contains(keyword))
+    }
+}
+
+/// Whether the asset `name` is one `rule` picks.
+pub fn is_picked_by(rule: &AssetRule, name: &str) -> anyhow::Result<bool> {
+    Ok(Picker::new(rule)?.picks(name))
+}
+
+/// A rule picking the asset `name` of the release `version`, and the equivalent asset of
+/// other releases, which embed their version in the name instead.
+pub fn rule_for(name: &str, version: &str) -> AssetRule {
+    // `-` needs no escape outside a class, the rule reads better without.
+    let escape = |s: &str| regex::escape(s).replace(r"\-", "-");
+    let mut include = escape(name);
+    let bare = version.trim_start_matches('v');
+    for version in [version, bare] {
+        if !version.is_empty() {
+            include = include.replace(&escape(version), ".+");
+        }
+    }
+    AssetRule {
+        include: Some(format!("^{}$", include)),
+        auto: true,
+        ..AssetRule::default()
+    }
+}
+
+/// The assets `up` would install: the one named `asset_name`, those the rule of the highest
+/// priority which picks any picks, or those fitting `system` best. Several if they fit
+/// equally well, none if none fits.
+pub fn select<'a, T>(
+    assets: &'a [T],
+    name: impl Fn(&T) -> &str,
+    asset_name: Option<&str>,
+    system: Option<&System>,
+    prefs: &Preferences,
+) -> anyhow::Result<Vec<&'a T>> {
+    if let Some(asset) = asset_name.and_then(|n| assets.iter().find(|asset| name(asset) == n)) {
+        return Ok(vec![asset]);
+    }
+    let mut pickers = prefs
+        .rules
+        .iter()
+        .map(Picker::new)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    pickers.sort_by_key(|picker| std::cmp::Reverse(picker.rule.priority));
+    // Saved from the asset chosen among those a rule picked, it breaks the tie whatever its
+    // priority.
+    let chosen = |asset: &&T| {
+        pickers
+            .iter()
+            .any(|picker| picker.rule.auto && picker.picks(name(asset)))
+    };
+    for picker in pickers.iter() {
+        let mut picked: Vec<_> = assets
+            .iter()
+            .filter(|asset| picker.picks(name(asset)))
+            .collect();
+        if picked.len() > 1 && picked.iter().any(chosen) {
+            picked.retain(chosen);
+        }
+        match picked.len() {
+            0 => continue,
+            1 => return Ok(picked),
+            _ => {}
+        }
+        // The rule names them, so even those which seem not to fit are kept.
+        let ranked = match system {
+            Some(system) => rank(&picked, |asset| name(asset), system, prefs),
+            None => Vec::new(),
+        };
+        return Ok(match best(&ranked) {
+            [] => picked,
+            best => best.iter().map(|ranked| *ranked.asset).collect(),
+        });
+    }
+    let ranked = match system {
+        Some(system) => rank(assets, name, system, prefs),
+        None => Vec::new(),
+    };
+    Ok(best(&ranked).iter().map(|ranked| ranked.asset).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysinfo::Arch;
+
+    #[test]
+    fn tokenize_names() {
+        let cases = [
+            (
+                "tool_Linux_x86_64.tar.gz",
+                &["tool", "linux", "x86", "64", "tar", "gz"][..],
+            ),
+            (
+                "tool-v1.2.0-aarch64-apple-darwin.zip",
+                &["tool", "v1", "2", "0", "aarch64", "apple", "darwin", "zip"][..],
+            ),
+            ("--", &[][..]),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(tokenize(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn traits_of_names() {
+        let ignore = [
+            "arm-tool".to_string(),
+            "v1.2.0".to_string(),
+            "1.2.0".to_string(),
+        ];
+        let cases = [
+            (
+                "arm-tool-v1.2.0-x86_64-unknown-linux-musl.tar.gz",
+                Some("linux"),
+                Some("x86_64"),
+                Some("musl"),
+                Kind::App,
+            ),
+            (
+                "arm-tool_1.2.0_Linux_arm64.tar.gz",
+                Some("linux"),
+                Some("aarch64"),
+                None,
+                Kind::App,
+            ),
+            (
+                "arm-tool-v1.2.0-armv7-unknown-linux-gnueabihf.tar.gz",
+                Some("linux"),
+                Some("armv7"),
+                Some("gnu"),
+                Kind::App,
+            ),
+            (
+                "arm-tool-v1.2.0-universal-apple-darwin.tar.gz",
+                Some("macos"),
+                Some("universal"),
+                None,
+                Kind::App,
+            ),
+            (
+                "arm-tool-v1.2.0-x86_64-pc-windows-msvc.zip",
+                Some("windows"),
+                Some("x86_64"),
+                Some("msvc"),
+                Kind::App,
+            ),
+            ("arm-tool.exe", Some("windows"), None, None, Kind::App),
+            (
+                "arm-tool_1.2.0_amd64.deb",
+                Some("linux"),
+                Some("x86_64"),
+                None,
+                Kind::Package,
+            ),
+            (
+                "arm-tool-1.2.0.x86_64.rpm",
+                Some("linux"),
+                Some("x86_64"),
+                None,
+                Kind::Package,
+            ),
+            (
+                "arm-tool-1.2.0.dmg",
+                Some("macos"),
+                None,
+                None,
+                Kind::Package,
+            ),
+            (
+                "arm-tool-1.2.0-x64.msi",
+                Some("windows"),
+                Some("x86_64"),
+                None,
+                Kind::Package,
+            ),
+            (
+                "arm-tool-deb-helper-linux-amd64",
+                Some("linux"),
+                Some("x86_64"),
+                None,
+                Kind::App,
+            ),
+            ("arm-tool-1.2.0-src.tar.gz", None, None, None, Kind::Source),
+            (
+                "arm-tool-v1.2.0-linux-amd64.tar.gz.sha256",
+                Some("linux"),
+                Some("x86_64"),
+                None,
+                Kind::Checksum,
+            ),
+            ("SHA256SUMS", None, None, None, Kind::Checksum),
+            (
+                "arm-tool-linux-amd64.tar.gz.sig",
+                Some("linux"),
+                Some("x86_64"),
+                None,
+                Kind::Signature,
+            ),
+            ("arm-tool.spdx.json", None, None, None, Kind::Metadata),
+            (
+                "arm-tool-linux-amd64-debug.tar.gz",
+                Some("linux"),
+                Some("x86_64"),
+                None,
+                Kind::Debug,
+            ),
+        ];
+        for (name, os, arch, libc, kind) in cases {
+            let expected = Traits {
+                os,
+                arch,
+                libc,
+                kind,
+            };
+            assert_eq!(traits(name, &ignore), expected, "{}", name);
+        }
+        // Not ignored, the name of the app tells an arch.
+        assert_eq!(traits("arm-tool.tar.gz", &[]).arch, Some("armv6"));
+    }
+
+    #[test]
+    fn score_on_hosts() {
+        let linux_gnu = System::of(OS::Linux, Arch::amd64, Some(Libc::Gnu), Some((2, 35)));
+        let old_gnu = System::of(OS::Linux, Arch::amd64, Some(Libc::Gnu), Some((2, 17)));
+        let linux_musl = System::of(OS::Linux, Arch::amd64, Some(Libc::Musl), None);
+        let armv7 = System::of(OS::Linux, Arch::armv7, Some(Libc::Gnu), Some((2, 35)));
+        let macos = System::of(OS::Darwin, Arch::aarch64, None, None);
+        let prefs = Preferences::default();
+        let cases = [
+            (&linux_gnu, "tool-x86_64-unknown-linux-gnu.tar.gz", Ok(10)),
+            (&linux_gnu, "tool-x86_64-unknown-linux-musl.tar.gz", Ok(8)),
+            (&linux_gnu, "tool-linux-amd64.tar.gz", Ok(9)),
+            (&linux_gnu, "tool_amd64.deb", Ok(7)),
+            (&linux_gnu, "tool-aarch64-apple-darwin.tar.gz", Err(())),
+            (&linux_gnu, "tool-linux-arm64.tar.gz", Err(())),
+            (&linux_gnu, "tool-x86_64-pc-windows-msvc.zip", Err(())),
+            (&linux_gnu, "tool.tar.gz.sha256", Err(())),
+            (&old_gnu, "tool-x86_64-unknown-linux-musl.tar.gz", Ok(10)),
+            (&old_gnu, "tool-x86_64-unknown-linux-gnu.tar.gz", Ok(8)),
+            (&linux_musl, "tool-x86_64-unknown-linux-gnu.tar.gz", Err(())),
+            (&linux_musl, "tool-x86_64-unknown-linux-musl.tar.gz", Ok(10)),
+            (&armv7, "tool-armv7-unknown-linux-gnueabihf.tar.gz", Ok(10)),
+            (&armv7, "tool-arm-unknown-linux-gnueabihf.tar.gz", Ok(8)),
+            (&macos, "tool-aarch64-apple-darwin.tar.gz", Ok(8)),
+            (&macos, "tool-universal-apple-darwin.tar.gz", Ok(6)),
+            (&macos, "tool.dmg", Ok(2)),
+            (&macos, "tool-x86_64-apple-darwin.tar.gz", Err(())),
+        ];
+        for (system, name, expected) in cases {
+            let score = score(&traits(name, &[]), system, &prefs).map_err(|_| ());
+            assert_eq!(score, expected, "{} on {:?}", name, system);
+        }
+        let musl = Preferences {
+            libc: Some(Libc::Musl),
+            ..Preferences::default()
+        };
+        let score = |name| score(&traits(name, &[]), &linux_gnu, &musl);
+        assert!(score("tool-linux-x86_64-musl.tar.gz") > score("tool-linux-x86_64-gnu.tar.gz"));
+    }
+
+    #[test]
+    fn rules_for_chosen_assets() {
+        let cases = [
+            (
+                "tool-v1.2.0-x86_64-linux.tar.gz",
+                "v1.2.0",
+                "^tool-.+-x86_64-linux\\.tar\\.gz$",
+            ),
+            (
+                "tool_1.2.0_linux_amd64.zip",
+                "v1.2.0",
+                "^tool_.+_linux_amd64\\.zip$",
+            ),
+            ("tool-linux", "v1.2.0", "^tool-linux$"),
+            ("tool-1.2.0", "", "^tool-1\\.2\\.0$"),
+        ];
+        for (name, version, include) in cases {
+            let rule = rule_for(name, version);
+            assert_eq!(rule.include.as_deref(), Some(include), "{}", name);
+            assert!(rule.auto);
+            assert!(is_picked_by(&rule, name).unwrap(), "{}", name);
+        }
+        let rule = rule_for("tool-v1.2.0-x86_64-linux.tar.gz", "v1.2.0");
+        assert!(is_picked_by(&rule, "tool-v1.3.0-x86_64-linux.tar.gz").unwrap());
+        assert!(!is_picked_by(&rule, "tool-v1.3.0-x86_64-linux.tar.gz.sha256").unwrap());
+    }
+
+    #[test]
+    fn picked_by_rules() {
+        let rule = |include: Option<&str>, exclude: Option<&str>, keywords: &[&str]| AssetRule {
+            include: include.map(String::from),
+            exclude: exclude.map(String::from),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            ..AssetRule::default()
+        };
+        let name = "tool-v1.0.0-x86_64-unknown-linux-musl.tar.gz";
+        let cases = [
+            (rule(None, None, &[]), true),
+            (rule(Some("musl"), None, &[]), true),
+            (rule(Some("^musl"), None, &[]), false),
+            (rule(Some("linux"), Some("musl"), &[]), false),
+            (rule(None, Some("gnu"), &["LINUX", "x86_64"]), true),
+            (rule(None, None, &["linux", "arm"]), false),
+        ];
+        for (rule, expected) in cases {
+            assert_eq!(is_picked_by(&rule, name).unwrap(), expected, "{:?}", rule);
+        }
+        assert!(is_picked_by(&rule(Some("("), None, &[]), name).is_err());
+    }
+
+    #[test]
+    fn select_assets() {
+        let assets = [
+            "tool-v1.0.0-x86_64-unknown-linux-gnu.tar.gz",
+            "tool-v1.0.0-x86_64-unknown-linux-musl.tar.gz",
+            "tool-v1.0.0-aarch64-unknown-linux-gnu.tar.gz",
+            "tool-v1.0.0-x86_64-apple-darwin.tar.gz",
+            "tool-v1.0.0-linux-x86_64.tar.gz",
+            "tool-v1.0.0-linux-amd64.tar.gz",
+            "SHA256SUMS",
+        ];
+        let system = System::of(OS::Linux, Arch::amd64, Some(Libc::Gnu), Some((2, 35)));
+        let rule = |include: &str, priority: i32| AssetRule {
+            include: Some(include.to_string()),
+            priority,
+            ..AssetRule::default()
+        };
+        let chosen = rule_for("tool-v1.0.0-linux-amd64.tar.gz", "v1.0.0");
+        let cases: [(Option<&str>, Vec<AssetRule>, &[&str]); 8] = [
+            (
+                None,
+                vec![],
+                &["tool-v1.0.0-x86_64-unknown-linux-gnu.tar.gz"],
+            ),
+            (
+                Some("tool-v1.0.0-x86_64-apple-darwin.tar.gz"),
+                vec![rule("musl", 1)],
+                &["tool-v1.0.0-x86_64-apple-darwin.tar.gz"],
+            ),
+            (
+                None,
+                vec![rule("musl", 0)],
+                &["tool-v1.0.0-x86_64-unknown-linux-musl.tar.gz"],
+            ),
+            (
+                None,
+                vec![rule("musl", 0), rule("aarch64", 1)],
+                &["tool-v1.0.0-aarch64-unknown-linux-gnu.tar.gz"],
+            ),
+            (
+                None,
+                vec![rule("riscv", 1)],
+                &["tool-v1.0.0-x86_64-unknown-linux-gnu.tar.gz"],
+            ),
+            (
+                None,
+                vec![rule("^tool-v1\\.0\\.0-linux-", 0)],
+                &[
+                    "tool-v1.0.0-linux-x86_64.tar.gz",
+                    "tool-v1.0.0-linux-amd64.tar.gz",
+                ],
+            ),
+            // the rule saved from the choice among those a rule of a higher priority picked
+            (
+                None,
+                vec![rule("^tool-v1\\.0\\.0-linux-", 1), chosen.clone()],
+                &["tool-v1.0.0-linux-amd64.tar.gz"],
+            ),
+            (
+                None,
+                vec![rule("musl|darwin", 1), chosen],
+                &["tool-v1.0.0-x86_64-unknown-linux-musl.tar.gz"],
+            ),
+        ];
+        for (asset_name, rules, expected) in cases {
+            let prefs = Preferences {
+                rules,
+                ..Preferences::default()
+            };
+            let selected = select(&assets, |name| name, asset_name, Some(&system), &prefs).unwrap();
+            assert_eq!(
+                selected,
+                expected.iter().collect::<Vec<_>>(),
+                "{:?}",
+                prefs.rules
+            );
+        }
+    }
+}
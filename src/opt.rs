@@ -5,6 +5,7 @@ use clap::{ArgEnum, Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 
 use crate::ghapi;
+use crate::sysinfo::Libc;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -24,14 +25,26 @@ pub enum Commands {
         name: String,
         /// Which version want to install. The latest version will be selected, if not specified.
         version: Option<String>,
-        #[clap(long, help = "Github repository, `{owner}/{name}`")]
+        #[clap(long, help = "Github repository, `[{host}/]{owner}/{name}`")]
         repo: String,
+        #[clap(long, help = "GitHub Enterprise Server host, e.g. `ghe.example.com`")]
+        host: Option<String>,
+        #[clap(long, help = "API base url, `https://{host}/api/v3/` by default")]
+        api_url: Option<String>,
         #[clap(long, help = "Check for the latest release including prerelease")]
         latest: bool,
         // #[clap(long, help = "Which version will be installed.")]
         // version: bool,
-        #[clap(long, help = "Which asset.")]
+        #[clap(
+            long,
+            help = "Which asset, may be a template like `tool-{{ version }}-{{ target }}.tar.gz`."
+        )]
         asset_name: Option<String>,
+        #[clap(
+            long,
+            help = "Download the asset from this url template instead of asking the API for the assets."
+        )]
+        url: Option<String>,
         #[clap(long, help = "Allow pre-release.")]
         allow_prerelease: bool,
         #[clap(long, help = "Do something after downloaded.")]
@@ -48,6 +61,14 @@ pub enum Commands {
         unpack: Option<String>,
         #[clap(long, help = "unpack as")]
         binname: Option<String>,
+        #[clap(
+            long,
+            arg_enum,
+            help = "Prefer assets linked against this libc over the one fitting the host."
+        )]
+        libc: Option<Libc>,
+        #[clap(flatten)]
+        signature: Box<SignatureArgs>,
     },
     Update {
         name: String,
@@ -55,7 +76,7 @@ pub enum Commands {
         version: Option<String>,
         #[clap(long, help = "Which asset.")]
         asset_name: Option<String>,
-        #[clap(long, help = "Github repository, `{owner}/{name}`")]
+        #[clap(long, help = "Github repository, `[{host}/]{owner}/{name}`")]
         repo: Option<String>,
         #[clap(long, help = "Allow pre-release.")]
         allow_prerelease: bool,
@@ -66,23 +87,125 @@ pub enum Commands {
     Upgrade {
         #[clap(long, help = "Allow pre-release.")]
         allow_prerelease: bool,
+        #[clap(
+            long,
+            short,
+            default_value = "4",
+            help = "How many apps to upgrade at once."
+        )]
+        jobs: usize,
+    },
+
+    Uninstall {
+        name: String,
+        #[clap(
+            long,
+            help = "Remove files even if they were modified after installation."
+        )]
+        force: bool,
+        #[clap(
+            long,
+            help = "Only remove the installed files, keep the app in the config."
+        )]
+        keep_config: bool,
+        #[clap(long, help = "Show what would be removed without removing anything.")]
+        dry_run: bool,
+    },
+
+    /// Show which apps have a newer release, without installing anything.
+    ///
+    /// Exits with status 10 if any update is available.
+    Outdated {
+        #[clap(
+            long,
+            short,
+            default_value = "4",
+            help = "How many apps to check at once."
+        )]
+        jobs: usize,
+    },
+
+    /// Show the remaining API quota of every host in use.
+    RateLimit,
+
+    /// List installed apps.
+    List {
+        #[clap(long, arg_enum, default_value = "text", help = "Output format.")]
+        format: Format,
+        #[clap(
+            long,
+            help = "List every installed version, which `up use` can activate."
+        )]
+        all_versions: bool,
+    },
+
+    /// Activate the version installed before the current one, without network access.
+    Rollback { name: String },
+
+    /// Activate an installed version of an app, its shim runs that one from now on.
+    Use { name: String, version: String },
+
+    /// Install the versions pinned by the `.up.toml` of the current project.
+    ///
+    /// Inside the project the shims run those versions.
+    Sync,
+
+    /// Run an app once without installing it, e.g. `up run owner/repo@v1.0.0 -- --help`.
+    ///
+    /// Its executable is cached, later runs of the same version need no network access.
+    Run {
+        #[clap(help = "Github repository and version, `[{host}/]{owner}/{name}[@{version}]`")]
+        target: String,
+        #[clap(long, help = "Which asset.")]
+        asset_name: Option<String>,
+        #[clap(long, help = "which to unpack")]
+        unpack: Option<String>,
+        #[clap(long, help = "Name of the executable, the repo name by default.")]
+        binname: Option<String>,
+        #[clap(last = true, parse(from_os_str), help = "Arguments of the app.")]
+        args: Vec<std::ffi::OsString>,
+    },
+
+    /// Show every asset of a release, where it runs and how well it fits this host.
+    Explain {
+        #[clap(help = "An app of up.toml, or a Github repository `[{host}/]{owner}/{name}`")]
+        repo: String,
+        /// The release to explain, the latest one if not specified.
+        version: Option<String>,
+        #[clap(long, arg_enum, default_value = "text", help = "Output format.")]
+        format: Format,
+    },
+
+    /// Print the shell hook which puts the versions pinned by the current project first
+    /// in `PATH`, e.g. `eval "$(up activate bash)"` in `~/.bashrc`.
+    Activate {
+        #[clap(arg_enum)]
+        shell: Shell,
+        #[clap(
+            long,
+            hide = true,
+            help = "Print the `PATH` changes for the current dir."
+        )]
+        env: bool,
+    },
+
+    /// Show everything known about an app.
+    Info {
+        name: String,
+        #[clap(long, arg_enum, default_value = "text", help = "Output format.")]
+        format: Format,
     },
 }
 
 #[non_exhaustive]
-#[derive(Clone, Debug, Deserialize, Serialize, ArgEnum)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ArgEnum)]
 pub enum Type {
     Font,
+    #[default]
     Executable,
     Configuration,
 }
 
-impl Default for Type {
-    fn default() -> Self {
-        Type::Executable
-    }
-}
-
 impl FromStr for Type {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -98,15 +221,36 @@ impl FromStr for Type {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ArgEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ArgEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Host of the public GitHub.
+pub const GITHUB: &str = "github.com";
+
 #[derive(Debug, Default)]
 pub struct Repo {
+    host: String,
     name: String,
     owner: String,
 }
 
 impl Repo {
-    pub fn new(owner: String, name: String) -> Repo {
-        Repo { name, owner }
+    pub fn new(host: String, owner: String, name: String) -> Repo {
+        Repo { host, name, owner }
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
     }
 
     pub fn name(&self) -> &str {
@@ -129,56 +273,255 @@ pub struct Options {
     pub latest: bool,
     pub version: Option<String>,
     pub asset_name: Option<String>,
+    pub url: Option<String>,
     pub allow_prerelease: bool,
     pub after_downloaded: Option<String>,
     pub r#type: Type,
     pub unpack: Option<String>,
     pub binname: Option<String>,
+    pub libc: Option<Libc>,
+    pub host: Option<String>,
+    pub api_url: Option<String>,
+    pub signature: Option<SignatureConfig>,
+    pub attestation: Option<AttestationConfig>,
+    pub rules: Vec<AssetRule>,
     pub app_version: Option<AppVersion>,
 }
 
 impl Options {
+    /// Options of an app of `repo` not in `up.toml`, named like the repo.
+    pub fn of_repo(repo: String) -> Options {
+        Options {
+            name: repo.rsplit('/').next().unwrap_or_default().to_string(),
+            repo,
+            latest: false,
+            version: None,
+            asset_name: None,
+            url: None,
+            allow_prerelease: false,
+            after_downloaded: None,
+            r#type: Type::default(),
+            unpack: None,
+            binname: None,
+            libc: None,
+            host: None,
+            api_url: None,
+            signature: None,
+            attestation: None,
+            rules: Vec::new(),
+            app_version: None,
+        }
+    }
+
     pub fn repo(&self) -> anyhow::Result<Repo> {
-        // anyhow::ensure!(
-        //     self.repo.is_some(),
-        //     "repo must in format `{{owner}}/{{name}}`"
-        // );
-        // let repo = repo.as_ref().unwrap();
+        let repo = self.repo.split('/').collect::<Vec<_>>();
+        let (host, owner, name) = match repo[..] {
+            [owner, name] => (self.host.as_deref().unwrap_or(GITHUB), owner, name),
+            [host, owner, name] => {
+                anyhow::ensure!(
+                    self.host.is_none() || self.host.as_deref() == Some(host),
+                    "repo `{}` is not on host `{}`",
+                    &self.repo,
+                    self.host.as_ref().unwrap()
+                );
+                (host, owner, name)
+            }
+            _ => anyhow::bail!("repo must in format `[{{host}}/]{{owner}}/{{name}}`"),
+        };
         anyhow::ensure!(
-            self.repo.contains("/"),
-            "repo must in format `{{owner}}/{{name}}`"
+            !owner.is_empty() && !name.is_empty(),
+            "repo must in format `[{{host}}/]{{owner}}/{{name}}`"
         );
-        let repo = self.repo.split("/").collect::<Vec<_>>();
-        anyhow::ensure!(repo.len() == 2, "repo must in format `{{owner}}/{{name}}`");
-        Ok(Repo::new(repo[0].to_string(), repo[1].to_string()))
+        Ok(Repo::new(
+            host.to_string(),
+            owner.to_string(),
+            name.to_string(),
+        ))
+    }
+
+    /// What the app prefers when matching the assets of the release `tag`.
+    pub fn preferences(&self, tag: Option<&str>) -> crate::matcher::Preferences {
+        let repo = self.repo.rsplit('/').next().unwrap_or_default();
+        let mut ignore = vec![self.name.clone(), repo.to_string()];
+        if let Some(tag) = tag {
+            ignore.extend([tag.to_string(), tag.trim_start_matches('v').to_string()]);
+        }
+        crate::matcher::Preferences {
+            libc: self.libc,
+            rules: self.rules.clone(),
+            ignore,
+        }
+    }
+
+    /// The API client for the host of the repo.
+    pub fn github(&self, config: &Config) -> anyhow::Result<ghapi::Github> {
+        let repo = self.repo()?;
+        config.github(repo.host(), self.api_url.as_deref())
     }
 }
 
-impl Into<AppConfig> for Options {
-    fn into(self) -> AppConfig {
+impl From<Options> for AppConfig {
+    fn from(opts: Options) -> AppConfig {
         AppConfig {
-            name: self.name,
-            repo: self.repo,
-            asset_name: self.asset_name.unwrap(),
-            allow_prerelease: self.allow_prerelease,
-            after_downloaded: self.after_downloaded,
-            r#type: self.r#type,
-            unpack: self.unpack,
-            binname: self.binname,
+            name: opts.name,
+            repo: opts.repo,
+            asset_name: opts.asset_name,
+            url: opts.url,
+            allow_prerelease: opts.allow_prerelease,
+            after_downloaded: opts.after_downloaded,
+            r#type: opts.r#type,
+            unpack: opts.unpack,
+            binname: opts.binname,
+            libc: opts.libc,
+            host: opts.host,
+            api_url: opts.api_url,
+            signature: opts.signature,
+            attestation: opts.attestation,
+            rules: opts.rules,
+        }
+    }
+}
+
+impl From<AppConfig> for Options {
+    fn from(cfg: AppConfig) -> Options {
+        Options {
+            name: cfg.name,
+            repo: cfg.repo,
+            latest: true,
+            version: None,
+            asset_name: cfg.asset_name,
+            url: cfg.url,
+            allow_prerelease: cfg.allow_prerelease,
+            after_downloaded: cfg.after_downloaded,
+            r#type: cfg.r#type,
+            unpack: cfg.unpack,
+            binname: cfg.binname,
+            libc: cfg.libc,
+            host: cfg.host,
+            api_url: cfg.api_url,
+            signature: cfg.signature,
+            attestation: cfg.attestation,
+            rules: cfg.rules,
+            app_version: None,
         }
     }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Config {
+    /// Token for the GitHub API, `GITHUB_TOKEN`/`GH_TOKEN` take precedence.
+    pub token: Option<String>,
+    /// How many versions of each app to keep in the store, 2 if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_versions: Option<usize>,
+    /// Settings of GitHub Enterprise Server hosts, only these get `GH_ENTERPRISE_TOKEN`.
+    #[serde(default, skip_serializing_if = "indexmap::IndexMap::is_empty")]
+    pub hosts: indexmap::IndexMap<String, HostConfig>,
     pub apps: indexmap::IndexMap<String, AppConfig>,
 }
 
+impl Config {
+    pub fn keep_versions(&self) -> usize {
+        self.keep_versions.unwrap_or(2)
+    }
+
+    /// The API client of `host`, authorized with the token of it if there is one.
+    ///
+    /// `api_url` overrides the one configured for the host.
+    pub fn github(&self, host: &str, api_url: Option<&str>) -> anyhow::Result<ghapi::Github> {
+        let cfg = self.hosts.get(host);
+        let configured = if host == GITHUB {
+            self.token.as_deref()
+        } else {
+            cfg.and_then(|cfg| cfg.token.as_deref())
+        };
+        let api_url = api_url
+            .or_else(|| cfg.and_then(|cfg| cfg.api_url.as_deref()))
+            .map(|api_url| {
+                // A trailing slash is significant for the base url.
+                format!("{}/", api_url.trim_end_matches('/'))
+            })
+            .unwrap_or_else(|| ghapi::api_url(host));
+        let token = crate::auth::token(host, configured, cfg.is_some());
+        ghapi::Github::new(&api_url, token)
+    }
+}
+
+/// A project manifest, `.up.toml`, pinning the versions of the apps a project uses.
+///
+/// It looks like the `apps` of `up.toml`, settings not set there are taken from it.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ProjectConfig {
+    #[serde(default)]
+    pub apps: indexmap::IndexMap<String, ProjectApp>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProjectApp {
+    /// The version the project uses.
+    pub version: String,
+    /// Github repository, only needed if the app is not in `up.toml`.
+    pub repo: Option<String>,
+    pub asset_name: Option<String>,
+    pub unpack: Option<String>,
+    pub binname: Option<String>,
+    pub host: Option<String>,
+    pub api_url: Option<String>,
+}
+
+impl ProjectApp {
+    /// Options to install the pinned version of `name`, on top of its `cfg` in `up.toml`.
+    pub fn options(&self, name: &str, cfg: Option<&AppConfig>) -> anyhow::Result<Options> {
+        let mut opts = match cfg {
+            Some(cfg) => Options::from(cfg.clone()),
+            None => Options {
+                name: name.to_string(),
+                ..Options::of_repo(self.repo.clone().ok_or_else(|| {
+                    anyhow::anyhow!("{} is not in up.toml, its `repo` must be set", name)
+                })?)
+            },
+        };
+        opts.latest = false;
+        opts.version = Some(self.version.clone());
+        if let Some(repo) = &self.repo {
+            opts.repo = repo.clone();
+        }
+        if self.asset_name.is_some() {
+            opts.asset_name = self.asset_name.clone();
+        }
+        if self.unpack.is_some() {
+            opts.unpack = self.unpack.clone();
+        }
+        if self.binname.is_some() {
+            opts.binname = self.binname.clone();
+        }
+        if self.host.is_some() {
+            opts.host = self.host.clone();
+        }
+        if self.api_url.is_some() {
+            opts.api_url = self.api_url.clone();
+        }
+        Ok(opts)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct HostConfig {
+    /// `https://{host}/api/v3/` if not set.
+    pub api_url: Option<String>,
+    pub token: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AppConfig {
     pub name: String,
     pub repo: String,
-    pub asset_name: String,
+    /// The asset to install, a template if it has `{{ }}`, see `template::Vars`. Chosen
+    /// by the rules if not set.
+    pub asset_name: Option<String>,
+    /// Template of the url to download the asset from, the assets of the release are not
+    /// listed then, saving a request to the API.
+    pub url: Option<String>,
     #[serde(default)]
     pub allow_prerelease: bool,
     pub after_downloaded: Option<String>,
@@ -186,6 +529,163 @@ pub struct AppConfig {
     pub r#type: Type,
     pub unpack: Option<String>,
     pub binname: Option<String>,
+    /// Prefer assets linked against this libc, e.g. `musl` for static builds, over the one
+    /// fitting the host.
+    pub libc: Option<Libc>,
+    /// GitHub Enterprise Server host, if not in `repo`.
+    pub host: Option<String>,
+    pub api_url: Option<String>,
+    /// Verify the asset by its signature before installing it.
+    ///
+    /// Keep the tables last, toml tables must come after plain values.
+    pub signature: Option<SignatureConfig>,
+    /// Verify the GitHub artifact attestation of the asset before installing it.
+    pub attestation: Option<AttestationConfig>,
+    /// Rules picking the asset of a release, tried by priority before `up` guesses.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<AssetRule>,
+}
+
+/// A rule picking the asset of an app among those of a release, by its name.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AssetRule {
+    /// Regex the name must match.
+    pub include: Option<String>,
+    /// Regex the name must not match.
+    pub exclude: Option<String>,
+    /// Words the name must contain, ignoring case.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// Rules of a higher priority are tried first.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
+    /// Saved from the asset chosen by hand, the next choice replaces it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto: bool,
+}
+
+fn is_zero(n: &i32) -> bool {
+    *n == 0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ArgEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureKind {
+    Minisign,
+    Gpg,
+    Cosign,
+}
+
+/// How the assets of an app are signed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SignatureConfig {
+    pub kind: SignatureKind,
+    /// Public key, a file in `<config dir>/keys`, or a base64 minisign public key.
+    pub key: Option<String>,
+    /// gpg: fingerprint of the key which must have made the signature.
+    pub fingerprint: Option<String>,
+    /// Name of the signature asset, `{asset}` is the name of the asset, e.g.
+    /// `{asset}.minisig`. The usual names of the kind if not set.
+    pub asset: Option<String>,
+    /// cosign keyless: regex of the certificate identity, e.g. the workflow url.
+    pub identity: Option<String>,
+    /// cosign keyless: OIDC issuer of the certificate.
+    pub issuer: Option<String>,
+    /// cosign keyless: sigstore trusted root, a file in `<config dir>/keys`,
+    /// `trusted_root.json` if not set.
+    pub trusted_root: Option<String>,
+}
+
+// Flags of `install` configuring the signature of an app, not a doc comment, clap would
+// show it as the help of `install`.
+#[derive(Clone, Debug, clap::Args)]
+pub struct SignatureArgs {
+    #[clap(
+        long = "signature",
+        arg_enum,
+        name = "signature",
+        value_name = "KIND",
+        help = "Verify the signature of the asset, made by this tool, before installing it."
+    )]
+    pub kind: Option<SignatureKind>,
+    #[clap(
+        long = "signature-key",
+        requires = "signature",
+        help = "Public key, a file in `<config dir>/keys`, or a base64 minisign public key."
+    )]
+    pub key: Option<String>,
+    #[clap(
+        long = "signature-fingerprint",
+        requires = "signature",
+        help = "gpg: fingerprint of the key which must have made the signature."
+    )]
+    pub fingerprint: Option<String>,
+    #[clap(
+        long = "signature-asset",
+        requires = "signature",
+        help = "Name of the signature asset, `{asset}` is the name of the asset."
+    )]
+    pub asset: Option<String>,
+    #[clap(
+        long = "signature-identity",
+        requires = "signature",
+        help = "cosign keyless: regex of the certificate identity."
+    )]
+    pub identity: Option<String>,
+    #[clap(
+        long = "signature-issuer",
+        requires = "signature",
+        help = "cosign keyless: OIDC issuer of the certificate."
+    )]
+    pub issuer: Option<String>,
+    #[clap(
+        long = "signature-trusted-root",
+        requires = "signature",
+        help = "cosign keyless: sigstore trusted root, a file in `<config dir>/keys`."
+    )]
+    pub trusted_root: Option<String>,
+}
+
+impl SignatureArgs {
+    /// The signature config, if a kind is given.
+    pub fn config(self) -> Option<SignatureConfig> {
+        Some(SignatureConfig {
+            kind: self.kind?,
+            key: self.key,
+            fingerprint: self.fingerprint,
+            asset: self.asset,
+            identity: self.identity,
+            issuer: self.issuer,
+            trusted_root: self.trusted_root,
+        })
+    }
+}
+
+/// How the build provenance of the assets of an app is attested.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AttestationConfig {
+    /// Regex the signing workflow identity must match, e.g.
+    /// `^https://github.com/owner/repo/.github/workflows/release.yml@refs/tags/`.
+    pub workflow: String,
+    /// Sigstore trusted root, a file in `<config dir>/keys`, `trusted_root.json` if not set.
+    pub trusted_root: Option<String>,
+    /// Whether to refuse an asset which is not verified, otherwise it is only recorded.
+    #[serde(default = "default_true")]
+    pub required: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Result of the attestation verification of an installed asset.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Attestation {
+    pub verified: bool,
+    /// The workflow which built the asset, `{repository}/{path}@{ref}`.
+    pub workflow: Option<String>,
+    /// Why it is not verified.
+    pub reason: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -193,12 +693,193 @@ pub struct AppVersion {
     pub name: String,
     pub version: String,
     pub files: Vec<std::path::PathBuf>,
+    /// Installed by `up sync` for a project which pins it, never dropped to keep fewer versions.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// The shims linked to `up` for `files` in the executable dir.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shims: Vec<std::path::PathBuf>,
+    /// The asset which was installed.
+    #[serde(default)]
+    pub asset: Option<String>,
+    /// Release notes of the installed version.
+    #[serde(default)]
+    pub release_notes: Option<String>,
+    /// Digest of the installed asset as `<algorithm>:<hex>`, verified by the published
+    /// checksums or the one GitHub reports, the one of the first download if neither is.
+    /// A reinstall of the same asset must match it, on any machine sharing the lock file.
+    #[serde(default)]
+    pub digest: Option<String>,
     pub updated_at: DateTime<Local>,
-    pub last_latest_at: Option<DateTime<Local>>,
-    pub last_releases_at: Option<DateTime<Local>>,
+    /// Attestation of the installed asset, if one is configured.
+    ///
+    /// Keep the tables last, toml tables must come after plain values.
+    #[serde(default)]
+    pub attestation: Option<Attestation>,
+    /// sha256 of each file in `files` right after it was written.
+    #[serde(default)]
+    pub checksums: indexmap::IndexMap<std::path::PathBuf, String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AppVersions {
+    /// The active version of each app, which its shim runs.
     pub apps: indexmap::IndexMap<String, AppVersion>,
+    /// Every version of each app in the store, the most recently installed first.
+    #[serde(default, skip_serializing_if = "indexmap::IndexMap::is_empty")]
+    pub versions: indexmap::IndexMap<String, Vec<AppVersion>>,
+}
+
+impl AppVersions {
+    /// Records the newly installed `app`, at most `keep` versions of it are kept besides the
+    /// pinned ones, the active one always is. Those no longer kept are returned.
+    pub fn add_version(&mut self, mut app: AppVersion, keep: usize) -> Vec<AppVersion> {
+        let active = self.apps.get(&app.name).map(|v| v.version.clone());
+        let versions = self.versions.entry(app.name.clone()).or_default();
+        versions.retain(|v| {
+            app.pinned |= v.version == app.version && v.pinned;
+            v.version != app.version
+        });
+        versions.insert(0, app);
+        let mut dropped = Vec::new();
+        let mut kept = 0;
+        versions.retain(|v| {
+            if v.pinned {
+                return true;
+            }
+            kept += 1;
+            if kept <= keep.max(1) || Some(&v.version) == active.as_ref() {
+                return true;
+            }
+            dropped.push(v.clone());
+            false
+        });
+        dropped
+    }
+
+    /// Marks the installed `version` of `name` as pinned by a project.
+    pub fn pin(&mut self, name: &str, version: &str) {
+        let versions = self.versions.get_mut(name).into_iter().flatten();
+        for installed in versions.filter(|v| v.version == version) {
+            installed.pinned = true;
+        }
+    }
+
+    /// The installed `version` of `name`.
+    pub fn version(&self, name: &str, version: &str) -> Option<&AppVersion> {
+        self.versions
+            .get(name)?
+            .iter()
+            .find(|v| v.version == version)
+    }
+
+    /// The version installed before the active one of `name`.
+    pub fn previous(&self, name: &str) -> Option<&AppVersion> {
+        let active = self.apps.get(name)?;
+        let versions = self.versions.get(name)?;
+        let position = versions.iter().position(|v| v.version == active.version)?;
+        versions.get(position + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app_version(version: &str) -> AppVersion {
+        AppVersion {
+            name: "tool".into(),
+            version: version.into(),
+            files: vec![],
+            pinned: false,
+            shims: vec![],
+            asset: None,
+            release_notes: None,
+            digest: None,
+            updated_at: Local::now(),
+            attestation: None,
+            checksums: Default::default(),
+        }
+    }
+
+    /// `installed` in the order of their installation, `active` the one the shims run.
+    fn app_versions(installed: &[&str], active: &str) -> AppVersions {
+        let mut versions = AppVersions {
+            apps: Default::default(),
+            versions: Default::default(),
+        };
+        for version in installed {
+            versions.add_version(app_version(version), usize::MAX);
+        }
+        versions.apps.insert("tool".into(), app_version(active));
+        versions
+    }
+
+    fn names(versions: &[AppVersion]) -> Vec<&str> {
+        versions.iter().map(|v| v.version.as_str()).collect()
+    }
+
+    #[test]
+    fn add_version() {
+        // installed, active, newly installed, keep, kept, dropped
+        type Case<'a> = (
+            &'a [&'a str],
+            &'a str,
+            &'a str,
+            usize,
+            &'a [&'a str],
+            &'a [&'a str],
+        );
+        let cases: &[Case] = &[
+            // over the limit
+            (&["1", "2", "3"], "3", "4", 2, &["4", "3"], &["2", "1"]),
+            (&["1", "2"], "2", "3", 0, &["3", "2"], &["1"]),
+            // the active version is kept beyond the limit
+            (&["1", "2", "3"], "1", "4", 2, &["4", "3", "1"], &["2"]),
+            // reinstalling an existing version
+            (&["1", "2", "3"], "3", "2", 3, &["2", "3", "1"], &[]),
+            (&["1", "2"], "2", "2", 1, &["2"], &["1"]),
+        ];
+        for (installed, active, new, keep, kept, dropped) in cases {
+            let mut versions = app_versions(installed, active);
+            let actual = versions.add_version(app_version(new), *keep);
+            assert_eq!(names(&actual), *dropped, "{:?} + {}", installed, new);
+            assert_eq!(names(&versions.versions["tool"]), *kept);
+        }
+    }
+
+    #[test]
+    fn pinned_versions_kept() {
+        // installed with the last one active, pinned, newly installed, kept
+        type Case<'a> = (&'a [&'a str], &'a [&'a str], &'a str, &'a [&'a str]);
+        let cases: &[Case] = &[
+            (&["1", "2", "3"], &["1"], "4", &["4", "3", "1"]),
+            (&["1", "2", "3"], &["2", "1"], "4", &["4", "3", "2", "1"]),
+            (&["1", "2", "3"], &["3"], "4", &["4", "3"]),
+            // a pinned version stays pinned when installed again
+            (&["1", "2", "3"], &["1"], "1", &["1", "3"]),
+        ];
+        for (installed, pinned, new, kept) in cases {
+            let mut versions = app_versions(installed, installed[installed.len() - 1]);
+            for version in versions.versions.get_mut("tool").unwrap().iter_mut() {
+                version.pinned = pinned.contains(&version.version.as_str());
+            }
+            versions.add_version(app_version(new), 1);
+            let actual = &versions.versions["tool"];
+            assert_eq!(names(actual), *kept, "{:?} pinned", pinned);
+            assert_eq!(actual[0].pinned, pinned.contains(new));
+        }
+    }
+
+    #[test]
+    fn previous() {
+        // after `use` or `rollback` made `active` the one the shims run
+        let cases = [("3", Some("2")), ("2", Some("1")), ("1", None), ("4", None)];
+        for (active, expected) in cases {
+            let versions = app_versions(&["1", "2", "3"], active);
+            let actual = versions.previous("tool").map(|v| v.version.as_str());
+            assert_eq!(actual, expected, "{}", active);
+        }
+        assert!(app_versions(&["1"], "1").previous("other").is_none());
+    }
 }
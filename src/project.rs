@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+
+use crate::opt::{AppVersions, ProjectConfig};
+
+/// File name of the project manifest.
+pub const FILE_NAME: &str = ".up.toml";
+
+/// The manifest of the project `dir` is in, the nearest `.up.toml` in it or its parents.
+pub fn find(dir: &Path) -> anyhow::Result<Option<(PathBuf, ProjectConfig)>> {
+    for dir in dir.ancestors() {
+        let path = dir.join(FILE_NAME);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(anyhow::anyhow!(err).context(format!("Can not read {}", path.display())))
+            }
+        };
+        let project = toml::de::from_str(&content)
+            .map_err(|err| anyhow::anyhow!(err).context(format!("Invalid {}", path.display())))?;
+        return Ok(Some((path, project)));
+    }
+    Ok(None)
+}
+
+/// The executable `name` of the version pinned by the project `dir` is in, `None` if the
+/// project pins no app with an executable of that name.
+pub fn resolve(
+    dir: &Path,
+    locked_versions: &AppVersions,
+    name: &str,
+) -> anyhow::Result<Option<PathBuf>> {
+    let (path, project) = match find(dir)? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let is_named = |file: &PathBuf| file.file_name().and_then(|n| n.to_str()) == Some(name);
+    for (app, pinned) in project.apps.iter() {
+        let provides = pinned.binname.as_deref().unwrap_or(app) == name
+            || locked_versions
+                .versions
+                .get(app)
+                .into_iter()
+                .flatten()
+                .any(|installed| installed.files.iter().any(is_named));
+        if !provides {
+            continue;
+        }
+        let executable = locked_versions
+            .version(app, &pinned.version)
+            .and_then(|installed| installed.files.iter().find(|file| is_named(file)));
+        return match executable {
+            Some(executable) => Ok(Some(executable.clone())),
+            None => anyhow::bail!(
+                "{} {} pinned by {} is not installed, run `up sync`",
+                app,
+                &pinned.version,
+                path.display()
+            ),
+        };
+    }
+    Ok(None)
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use chrono::prelude::*;
+use serde::Deserialize;
+
+/// Longest `Retry-After` of a secondary rate limit which is waited out instead of failing.
+pub const MAX_RETRY_AFTER: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Quota of the API requests, from the `X-RateLimit-*` headers or the `/rate_limit` endpoint.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    pub fn from_headers(resp: &surf::Response) -> Option<RateLimit> {
+        let header =
+            |name: &str| -> Option<i64> { resp.header(name)?.as_str().trim().parse().ok() };
+        Some(RateLimit {
+            limit: header("x-ratelimit-limit").unwrap_or_default() as _,
+            remaining: header("x-ratelimit-remaining")? as _,
+            reset: Utc
+                .timestamp_opt(header("x-ratelimit-reset")?, 0)
+                .single()?,
+        })
+    }
+
+    pub fn reset_at(&self) -> DateTime<Local> {
+        self.reset.into()
+    }
+}
+
+/// Seconds to wait from the `Retry-After` header of a secondary rate limit.
+pub fn retry_after(resp: &surf::Response) -> Option<std::time::Duration> {
+    let secs = resp.header("retry-after")?.as_str().trim().parse().ok()?;
+    Some(std::time::Duration::from_secs(secs))
+}
+
+/// The last known quota of an API, and how many of it are reserved.
+#[derive(Debug, Default)]
+struct Quota {
+    limit: Option<RateLimit>,
+    /// Requests reserved and not sent yet, the quota the server reports does not know them.
+    reserved: u64,
+    /// Requests sent and not answered yet, nor do they count in the reported quota.
+    in_flight: u64,
+}
+
+impl Quota {
+    /// Whether `requests` more are left, beyond those reserved or in flight.
+    fn allows(&self, requests: u64) -> Result<(), RateLimit> {
+        match self.limit {
+            Some(limit)
+                if limit.reset > Utc::now()
+                    && limit.remaining < self.reserved + self.in_flight + requests =>
+            {
+                Err(limit)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The quota of each API, shared by all clients of it.
+fn known() -> &'static Mutex<HashMap<String, Quota>> {
+    static KNOWN: OnceLock<Mutex<HashMap<String, Quota>>> = OnceLock::new();
+    KNOWN.get_or_init(Default::default)
+}
+
+pub fn get(api: &str) -> Option<RateLimit> {
+    known()
+        .lock()
+        .unwrap()
+        .get(api)
+        .and_then(|quota| quota.limit)
+}
+
+/// Records the quota the server reported, reservations are kept.
+pub fn set(api: &str, limit: RateLimit) {
+    let mut known = known().lock().unwrap();
+    known.entry(api.to_string()).or_default().limit = Some(limit);
+}
+
+/// Requests of `api` reserved for one task, those not sent are given back once dropped.
+#[derive(Debug)]
+pub struct Reservation {
+    api: String,
+    left: AtomicU64,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut known = known().lock().unwrap();
+        if let Some(quota) = known.get_mut(&self.api) {
+            quota.reserved = quota.reserved.saturating_sub(*self.left.get_mut());
+        }
+    }
+}
+
+/// Reserves `requests` of the quota of `api`, which no other task may use then.
+///
+/// Fails with the quota if not that many are left, unknown quotas are not limited.
+pub fn reserve(api: &str, requests: u64) -> Result<Reservation, RateLimit> {
+    let mut known = known().lock().unwrap();
+    let quota = known.entry(api.to_string()).or_default();
+    quota.allows(requests)?;
+    quota.reserved += requests;
+    Ok(Reservation {
+        api: api.to_string(),
+        left: AtomicU64::new(requests),
+    })
+}
+
+/// A request sent to `api`, until the quota reported with its response is `set`.
+#[derive(Debug)]
+pub struct InFlight {
+    api: String,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut known = known().lock().unwrap();
+        if let Some(quota) = known.get_mut(&self.api) {
+            quota.in_flight = quota.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+/// Takes a request to send from `reservation`, or from the quota of `api` nobody reserved.
+///
+/// Fails with the quota if none is left.
+pub fn take(api: &str, reservation: Option<&Reservation>) -> Result<InFlight, RateLimit> {
+    let mut known = known().lock().unwrap();
+    let quota = known.entry(api.to_string()).or_default();
+    let reserved = reservation.filter(|reservation| reservation.left.load(Ordering::SeqCst) > 0);
+    match reserved {
+        Some(reservation) => {
+            reservation.left.fetch_sub(1, Ordering::SeqCst);
+            quota.reserved = quota.reserved.saturating_sub(1);
+        }
+        None => quota.allows(1)?,
+    }
+    quota.in_flight += 1;
+    Ok(InFlight {
+        api: api.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The quotas are shared, each test uses an api of its own.
+    fn limit(remaining: u64, reset_in: i64) -> RateLimit {
+        RateLimit {
+            limit: 60,
+            remaining,
+            reset: Utc::now() + chrono::Duration::seconds(reset_in),
+        }
+    }
+
+    #[test]
+    fn reserve_beyond_remaining() {
+        let api = "reserve_beyond_remaining";
+        set(api, limit(5, 3600));
+        let cases = [(3, true), (3, false), (2, true), (1, false)];
+        let mut reservations = Vec::new();
+        for (requests, ok) in cases {
+            let reserved = reserve(api, requests);
+            assert_eq!(reserved.is_ok(), ok, "{} of 5", requests);
+            reservations.extend(reserved.ok());
+        }
+        // Requests in flight count too, until the server reports them.
+        drop(reservations);
+        let _in_flight = take(api, None).unwrap();
+        assert!(reserve(api, 5).is_err());
+        assert!(reserve(api, 4).is_ok());
+    }
+
+    #[test]
+    fn reservations_given_back_on_drop() {
+        let api = "reservations_given_back_on_drop";
+        set(api, limit(3, 3600));
+        let reservation = reserve(api, 3).unwrap();
+        assert!(take(api, None).is_err());
+        // Taken from the reservation, the unsent rest is given back.
+        drop(take(api, Some(&reservation)).unwrap());
+        drop(reservation);
+        assert!(reserve(api, 3).is_ok());
+        assert!(reserve(api, 3).is_ok());
+        // The server reported the request which was sent.
+        set(api, limit(2, 3600));
+        assert!(reserve(api, 3).is_err());
+        assert!(reserve(api, 2).is_ok());
+    }
+
+    #[test]
+    fn takes_after_reset() {
+        let api = "takes_after_reset";
+        set(api, limit(0, 3600));
+        assert!(take(api, None).is_err());
+        assert!(reserve(api, 1).is_err());
+        set(api, limit(0, -1));
+        let _in_flight = take(api, None).unwrap();
+        assert!(reserve(api, 10).is_ok());
+        // Unknown quotas are not limited.
+        assert!(take("takes_after_reset unknown", None).is_ok());
+    }
+}
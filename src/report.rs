@@ -0,0 +1,358 @@
+use std::path::PathBuf;
+
+use chrono::prelude::*;
+use serde::Serialize;
+
+use crate::archive::ArchiveFormat;
+use crate::matcher::{self, Kind, Preferences};
+use crate::opt::{AppConfig, AppVersion, Attestation, Format};
+use crate::sysinfo::System;
+
+/// One line of `up list`.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub name: String,
+    pub repo: Option<String>,
+    pub version: Option<String>,
+    pub installed_at: Option<DateTime<Local>>,
+    pub path: Option<PathBuf>,
+}
+
+impl Summary {
+    pub fn new(name: &str, cfg: Option<&AppConfig>, installed: Option<&AppVersion>) -> Summary {
+        Summary {
+            name: name.to_string(),
+            repo: cfg.map(|cfg| cfg.repo.clone()),
+            version: installed.map(|v| v.version.clone()),
+            installed_at: installed.map(|v| v.updated_at),
+            path: installed.and_then(|v| v.files.first().cloned()),
+        }
+    }
+}
+
+/// One line of `up list --all-versions`.
+#[derive(Debug, Serialize)]
+pub struct Version {
+    pub name: String,
+    pub version: String,
+    pub installed_at: DateTime<Local>,
+    pub current: bool,
+    pub path: PathBuf,
+}
+
+impl Version {
+    pub fn new(version: &AppVersion, current: bool, path: PathBuf) -> Version {
+        Version {
+            name: version.name.clone(),
+            version: version.version.clone(),
+            installed_at: version.updated_at,
+            current,
+            path,
+        }
+    }
+}
+
+/// One asset of `up explain`.
+#[derive(Debug, Serialize)]
+pub struct Explained {
+    pub name: String,
+    pub os: Option<&'static str>,
+    pub arch: Option<&'static str>,
+    pub libc: Option<&'static str>,
+    pub kind: Kind,
+    pub archive: Option<String>,
+    /// How well it fits the host, `None` if it does not.
+    pub score: Option<i32>,
+    pub rejected: Option<String>,
+}
+
+/// Why `up` picks which asset of a release.
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    pub repo: String,
+    pub release: String,
+    pub host: String,
+    pub assets: Vec<Explained>,
+    /// The asset `up` would install, or those it would ask to choose from if several fit
+    /// equally well. Empty if none fits, it would ask to choose from all then.
+    pub picks: Vec<String>,
+}
+
+impl Explanation {
+    pub fn new<'a>(
+        repo: &str,
+        release: &str,
+        assets: impl Iterator<Item = &'a str>,
+        asset_name: Option<&str>,
+        system: &System,
+        prefs: &Preferences,
+    ) -> anyhow::Result<Explanation> {
+        let assets: Vec<_> = assets
+            .map(|name| {
+                let traits = matcher::traits(name, &prefs.ignore);
+                let score = matcher::score(&traits, system, prefs);
+                Explained {
+                    name: name.to_string(),
+                    os: traits.os,
+                    arch: traits.arch,
+                    libc: traits.libc,
+                    kind: traits.kind,
+                    archive: ArchiveFormat::from_filename(name.to_ascii_lowercase())
+                        .ok()
+                        .map(|format| format!("{:?}", format)),
+                    score: score.as_ref().ok().copied(),
+                    rejected: score.err(),
+                }
+            })
+            .collect();
+        let names: Vec<_> = assets.iter().map(|asset| asset.name.clone()).collect();
+        let picks = matcher::select(
+            &names,
+            |name| name.as_str(),
+            asset_name,
+            Some(system),
+            prefs,
+        )?
+        .into_iter()
+        .cloned()
+        .collect();
+        Ok(Explanation {
+            repo: repo.to_string(),
+            release: release.to_string(),
+            host: host(system),
+            assets,
+            picks,
+        })
+    }
+}
+
+/// Like `linux x86_64 gnu 2.36`.
+fn host(system: &System) -> String {
+    let mut host = format!("{} {}", system.os().name(), system.arch().name());
+    if let Some(libc) = system.libc() {
+        host = format!("{} {}", host, libc.name());
+    }
+    if let Some((major, minor)) = system.glibc_version() {
+        host = format!("{} {}.{}", host, major, minor);
+    }
+    host
+}
+
+/// A tracked file and its current size, `None` if it is gone.
+#[derive(Debug, Serialize)]
+pub struct File {
+    pub path: PathBuf,
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Installed {
+    pub version: String,
+    pub installed_at: DateTime<Local>,
+    pub asset: Option<String>,
+    pub digest: Option<String>,
+    pub attestation: Option<Attestation>,
+    pub files: Vec<File>,
+    pub release_notes: Option<String>,
+}
+
+/// Everything `up info` knows about an app.
+#[derive(Debug, Serialize)]
+pub struct Info {
+    pub name: String,
+    pub config: Option<AppConfig>,
+    pub installed: Option<Installed>,
+}
+
+impl Info {
+    pub fn new(name: &str, cfg: Option<&AppConfig>, installed: Option<&AppVersion>) -> Info {
+        let installed = installed.map(|v| Installed {
+            version: v.version.clone(),
+            installed_at: v.updated_at,
+            asset: v.asset.clone(),
+            digest: v.digest.clone(),
+            attestation: v.attestation.clone(),
+            files: v
+                .files
+                .iter()
+                .map(|path| File {
+                    path: path.clone(),
+                    size: std::fs::metadata(path).ok().map(|meta| meta.len()),
+                })
+                .collect(),
+            release_notes: v.release_notes.clone(),
+        });
+        Info {
+            name: name.to_string(),
+            config: cfg.cloned(),
+            installed,
+        }
+    }
+}
+
+fn or_dash<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(|| "-".into())
+}
+
+pub fn list(summaries: &[Summary], format: Format) -> anyhow::Result<()> {
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(summaries)?);
+        return Ok(());
+    }
+    let rows: Vec<[String; 5]> = summaries
+        .iter()
+        .map(|s| {
+            [
+                s.name.clone(),
+                or_dash(s.repo.as_ref()),
+                or_dash(s.version.as_ref()),
+                or_dash(s.installed_at.map(|at| at.format("%F %T"))),
+                or_dash(s.path.as_ref().map(|path| path.display())),
+            ]
+        })
+        .collect();
+    table(["NAME", "REPO", "VERSION", "INSTALLED", "PATH"], &rows);
+    Ok(())
+}
+
+pub fn versions(versions: &[Version], format: Format) -> anyhow::Result<()> {
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(versions)?);
+        return Ok(());
+    }
+    let rows: Vec<[String; 5]> = versions
+        .iter()
+        .map(|v| {
+            [
+                v.name.clone(),
+                v.version.clone(),
+                if v.current { "*".into() } else { String::new() },
+                v.installed_at.format("%F %T").to_string(),
+                v.path.display().to_string(),
+            ]
+        })
+        .collect();
+    table(["NAME", "VERSION", "CURRENT", "INSTALLED", "PATH"], &rows);
+    Ok(())
+}
+
+/// Prints `rows` under `header` in aligned columns.
+fn table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let header = header.map(String::from);
+    let mut widths = [0; N];
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+pub fn info(info: &Info, format: Format) -> anyhow::Result<()> {
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(info)?);
+        return Ok(());
+    }
+    println!("{}", &info.name);
+    if let Some(cfg) = &info.config {
+        println!();
+        print!("{}", toml::ser::to_string_pretty(cfg)?);
+    }
+    match &info.installed {
+        Some(installed) => {
+            println!();
+            println!("version = {}", &installed.version);
+            println!("installed_at = {}", installed.installed_at.format("%F %T"));
+            println!("asset = {}", or_dash(installed.asset.as_ref()));
+            println!("digest = {}", or_dash(installed.digest.as_ref()));
+            if let Some(attestation) = &installed.attestation {
+                match (attestation.verified, &attestation.reason) {
+                    (true, _) => println!(
+                        "attestation = verified, built by {}",
+                        or_dash(attestation.workflow.as_ref())
+                    ),
+                    (false, reason) => {
+                        println!("attestation = unverified: {}", or_dash(reason.as_ref()))
+                    }
+                }
+            }
+            println!("files:");
+            for file in installed.files.iter() {
+                let size = file
+                    .size
+                    .map(|size| bytesize::ByteSize::b(size).to_string_as(true))
+                    .unwrap_or_else(|| "missing".into());
+                println!("    {}\t({})", file.path.display(), size);
+            }
+            if let Some(notes) = &installed.release_notes {
+                println!();
+                println!("{}", notes);
+            }
+        }
+        None => {
+            println!();
+            println!("not installed");
+        }
+    }
+    Ok(())
+}
+
+pub fn explain(explanation: &Explanation, format: Format) -> anyhow::Result<()> {
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(explanation)?);
+        return Ok(());
+    }
+    println!(
+        "{} {} on {}",
+        &explanation.repo, &explanation.release, &explanation.host
+    );
+    println!();
+    let rows: Vec<[String; 8]> = explanation
+        .assets
+        .iter()
+        .map(|asset| {
+            [
+                if explanation.picks.contains(&asset.name) {
+                    "*".into()
+                } else {
+                    String::new()
+                },
+                asset.name.clone(),
+                or_dash(asset.os),
+                or_dash(asset.arch),
+                or_dash(asset.libc),
+                asset.kind.name().to_string(),
+                or_dash(asset.archive.as_ref()),
+                match (&asset.score, &asset.rejected) {
+                    (Some(score), _) => score.to_string(),
+                    (None, Some(reason)) => format!("rejected, {}", reason),
+                    (None, None) => "-".into(),
+                },
+            ]
+        })
+        .collect();
+    table(
+        [
+            "", "ASSET", "OS", "ARCH", "LIBC", "KIND", "ARCHIVE", "SCORE",
+        ],
+        &rows,
+    );
+    println!();
+    match &explanation.picks[..] {
+        [] => println!("No asset fits, up would ask to choose one of all."),
+        [pick] => println!("up would install {}", pick),
+        picks => println!(
+            "{} assets fit equally well, up would ask to choose one: {}",
+            picks.len(),
+            picks.join(", ")
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// What a run of an app resolved to, so the next one needs no network access.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Entry {
+    key: String,
+    /// Dir of the executable, named by the asset it was extracted from and what of it.
+    dir: String,
+    binname: String,
+}
+
+/// Executables of `up run`, each under `<cache dir>/run/<sha256 of its asset>/`, and an
+/// index from what was asked for to them. One unpacked by a pattern has the hash of it
+/// appended to the dir, other members of the same asset go elsewhere.
+#[derive(Clone, Debug)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache::new(crate::project_dirs().cache_dir().join("run"))
+    }
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Cache {
+        Cache { dir: dir.into() }
+    }
+
+    fn index_path(&self, key: &str) -> PathBuf {
+        let name = format!("{:x}", Sha256::digest(key.as_bytes()));
+        self.dir.join("index").join(name).with_extension("json")
+    }
+
+    /// Where the executable `binname`, `unpack`ed from the asset with the hex `sha256`, goes.
+    pub fn executable(&self, sha256: &str, unpack: Option<&str>, binname: &str) -> PathBuf {
+        let dir = match unpack {
+            Some(unpack) => {
+                let unpack = format!("{:x}", Sha256::digest(unpack.as_bytes()));
+                format!("{}-{}", sha256, &unpack[..16])
+            }
+            None => sha256.to_string(),
+        };
+        self.dir.join(dir).join(binname)
+    }
+
+    /// The cached executable of `key`, a missing or unreadable one is a miss.
+    pub async fn get(&self, key: &str) -> Option<PathBuf> {
+        let content = tokio::fs::read(self.index_path(key)).await.ok()?;
+        let entry = serde_json::from_slice::<Entry>(&content)
+            .ok()
+            .filter(|entry| entry.key == key)?;
+        let executable = self.dir.join(&entry.dir).join(&entry.binname);
+        executable.exists().then_some(executable)
+    }
+
+    /// Records `executable`, from `executable()`, as the one of `key`.
+    pub async fn put(&self, key: &str, executable: &Path) -> anyhow::Result<()> {
+        let entry = Entry {
+            key: key.to_string(),
+            dir: executable
+                .parent()
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            binname: executable
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let path = self.index_path(key);
+        tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+        let staging = path.with_extension(format!("{}.tmp", std::process::id()));
+        tokio::fs::write(&staging, serde_json::to_vec(&entry)?).await?;
+        tokio::fs::rename(&staging, &path).await?;
+        Ok(())
+    }
+}
+
+/// Sends what `up` prints to stderr until dropped, so stdout carries the output of the
+/// app only.
+pub struct StdoutToStderr {
+    saved: std::os::unix::io::RawFd,
+}
+
+impl StdoutToStderr {
+    pub fn new() -> anyhow::Result<StdoutToStderr> {
+        use std::io::Write;
+
+        std::io::stdout().flush()?;
+        let saved = nix::unistd::dup(1)?;
+        nix::unistd::dup2(2, 1)?;
+        Ok(StdoutToStderr { saved })
+    }
+}
+
+impl Drop for StdoutToStderr {
+    fn drop(&mut self) {
+        use std::io::Write;
+
+        std::io::stdout().flush().ok();
+        nix::unistd::dup2(self.saved, 1).ok();
+        nix::unistd::close(self.saved).ok();
+    }
+}
+
+/// Replaces this process by `executable` with `args`, its exit code and stdio are the ones
+/// of `up` then.
+pub fn exec(executable: &Path, args: &[OsString]) -> anyhow::Error {
+    use std::os::unix::process::CommandExt;
+
+    let err = std::process::Command::new(executable).args(args).exec();
+    anyhow::anyhow!(err).context(format!("Can not run {}", executable.display()))
+}
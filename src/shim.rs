@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+
+use crate::opt::AppVersions;
+
+/// The name `up` was invoked as, if it is not `up` itself but a shim of an app.
+///
+/// Shims are symlinks to `up` in the executable dir, named like the executable of an app.
+pub fn invoked_as() -> Option<String> {
+    let argv0 = PathBuf::from(std::env::args_os().next()?);
+    let name = argv0.file_name()?.to_string_lossy().into_owned();
+    let exe = std::env::current_exe().ok()?;
+    if Some(name.as_str()) == exe.file_name().and_then(|name| name.to_str()) {
+        return None;
+    }
+    // `up` copied or linked under another name is still `up`.
+    let shim = directories::BaseDirs::new()?.executable_dir()?.join(&name);
+    is_shim(&shim, &[]).then_some(name)
+}
+
+/// The shim of the app executable `file`, in the executable dir.
+pub fn path_of(file: &Path) -> Option<PathBuf> {
+    let dirs = directories::BaseDirs::new()?;
+    Some(dirs.executable_dir()?.join(file.file_name()?))
+}
+
+/// Points the shim at `path` to `up`, atomically replacing a shim, one of the `shims`
+/// recorded for the app or one of the `owned` files `up` installed there before, never
+/// anything else.
+pub async fn link(path: &Path, owned: &[PathBuf], shims: &[PathBuf]) -> anyhow::Result<()> {
+    let exe = std::env::current_exe()?;
+    if tokio::fs::read_link(path).await.ok().as_ref() == Some(&exe) {
+        return Ok(());
+    }
+    anyhow::ensure!(
+        tokio::fs::symlink_metadata(path).await.is_err()
+            || owned.iter().any(|file| file == path)
+            || is_shim(path, shims),
+        "{} is not installed by up, refusing to replace it by a shim",
+        path.display()
+    );
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let staging = path.with_file_name(format!(".{}.up-shim", name));
+    tokio::fs::remove_file(&staging).await.ok();
+    tokio::fs::symlink(&exe, &staging).await?;
+    tokio::fs::rename(&staging, path).await.map_err(|err| {
+        anyhow::anyhow!(err).context(format!("Can not create shim {}", path.display()))
+    })
+}
+
+/// Whether `path` is a shim `up` may remove or replace, a symlink recorded in `shims` or
+/// one resolving to this `up`.
+///
+/// A recorded shim is one even if `up` moved since and it dangles.
+pub fn is_shim(path: &Path, shims: &[PathBuf]) -> bool {
+    if !std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink()) {
+        return false;
+    }
+    if shims.iter().any(|shim| shim == path) {
+        return true;
+    }
+    match (std::fs::canonicalize(path), std::env::current_exe()) {
+        (Ok(target), Ok(exe)) => std::fs::canonicalize(exe).is_ok_and(|exe| exe == target),
+        _ => false,
+    }
+}
+
+/// Re-points the shims of the active apps which no longer link to this `up`, after it
+/// moved or was upgraded to another path.
+pub async fn repoint(locked_versions: &AppVersions) -> anyhow::Result<()> {
+    let exe = std::env::current_exe()?;
+    for app in locked_versions.apps.values() {
+        for shim in app.shims.iter() {
+            match tokio::fs::read_link(shim).await {
+                Ok(target) if target != exe => link(shim, &[], &app.shims).await?,
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The executable of the active version of the app which has one named `name`.
+pub fn resolve(locked_versions: &AppVersions, name: &str) -> Option<PathBuf> {
+    locked_versions
+        .apps
+        .values()
+        .flat_map(|app| app.files.iter())
+        .find(|file| file.file_name().and_then(|n| n.to_str()) == Some(name))
+        .cloned()
+}
+
+/// Runs the executable behind the shim `name` with the arguments of this process, the
+/// version pinned by the project of the current dir if there is one.
+pub fn exec(name: &str, locked_versions: &AppVersions) -> anyhow::Error {
+    use std::os::unix::process::CommandExt;
+
+    let pinned = match std::env::current_dir() {
+        Ok(dir) => crate::project::resolve(&dir, locked_versions, name),
+        Err(_) => Ok(None),
+    };
+    let executable = match pinned {
+        Ok(Some(executable)) => executable,
+        Ok(None) => match resolve(locked_versions, name) {
+            Some(executable) => executable,
+            None => return anyhow::anyhow!("{} is not installed by up", name),
+        },
+        Err(err) => return err,
+    };
+    let err = std::process::Command::new(&executable)
+        .arg0(name)
+        .args(std::env::args_os().skip(1))
+        .exec();
+    anyhow::anyhow!(err).context(format!("Can not run {}", executable.display()))
+}
@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+use crate::opt::{SignatureConfig, SignatureKind};
+
+/// Where public keys for signature verification are kept, `<config dir>/keys`.
+pub fn keys_dir() -> PathBuf {
+    crate::project_dirs().config_dir().join("keys")
+}
+
+/// The sigstore trusted root `name` in the keys dir, `trusted_root.json` if not set, which
+/// lets `cosign` verify offline.
+pub fn trusted_root(name: Option<&str>) -> anyhow::Result<PathBuf> {
+    let path = keys_dir().join(name.unwrap_or("trusted_root.json"));
+    anyhow::ensure!(path.exists(), "trusted root {} not found", path.display());
+    Ok(path)
+}
+
+/// Names of the signature asset of `asset`, configured or the usual ones of the kind.
+pub fn asset_names(cfg: &SignatureConfig, asset: &str) -> Vec<String> {
+    let patterns: &[&str] = match (&cfg.asset, cfg.kind) {
+        (Some(pattern), _) => return vec![pattern.replace("{asset}", asset)],
+        (None, SignatureKind::Minisign) => &["{asset}.minisig"],
+        (None, SignatureKind::Gpg) => &["{asset}.sig", "{asset}.asc", "{asset}.gpg"],
+        (None, SignatureKind::Cosign) => &["{asset}.bundle", "{asset}.sigstore.json"],
+    };
+    patterns
+        .iter()
+        .map(|pattern| pattern.replace("{asset}", asset))
+        .collect()
+}
+
+/// Verifies `file` by its detached `signature` as configured by `cfg`.
+pub async fn verify(cfg: &SignatureConfig, file: &Path, signature: &Path) -> anyhow::Result<()> {
+    match cfg.kind {
+        SignatureKind::Minisign => minisign(cfg, file, signature).await,
+        SignatureKind::Gpg => gpg(cfg, file, signature).await,
+        SignatureKind::Cosign => cosign(cfg, file, signature).await,
+    }
+}
+
+fn key_path(cfg: &SignatureConfig) -> anyhow::Result<PathBuf> {
+    let key = cfg.key.as_ref().ok_or_else(|| {
+        anyhow::anyhow!("no `key` configured to verify {:?} signatures", cfg.kind)
+    })?;
+    let path = keys_dir().join(key);
+    anyhow::ensure!(path.exists(), "key {} not found", path.display());
+    Ok(path)
+}
+
+/// `key` is a file in the keys dir, as written by `minisign -G`, or the base64 public key.
+async fn minisign(cfg: &SignatureConfig, file: &Path, signature: &Path) -> anyhow::Result<()> {
+    let key = cfg
+        .key
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("no `key` configured to verify minisign signatures"))?;
+    let path = keys_dir().join(key);
+    let key = if path.is_file() {
+        minisign_verify::PublicKey::decode(&tokio::fs::read_to_string(&path).await?)
+    } else {
+        minisign_verify::PublicKey::from_base64(key)
+    }
+    .map_err(|err| anyhow::anyhow!("invalid minisign public key `{}`: {}", key, err))?;
+    let signature =
+        minisign_verify::Signature::decode(&tokio::fs::read_to_string(signature).await?)
+            .map_err(|err| anyhow::anyhow!("invalid minisign signature: {}", err))?;
+    let content = tokio::fs::read(file).await?;
+    key.verify(&content, &signature, false)
+        .map_err(|err| anyhow::anyhow!("minisign: {}", err))
+}
+
+/// Runs `program`, failing with its output if it does not succeed.
+pub(crate) async fn run<S: AsRef<std::ffi::OsStr>>(
+    program: &str,
+    args: &[S],
+) -> anyhow::Result<String> {
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .map_err(|err| anyhow::anyhow!(err).context(format!("Can not run `{}`", program)))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    anyhow::ensure!(
+        output.status.success(),
+        "{}: {}{}",
+        program,
+        String::from_utf8_lossy(&output.stderr).trim(),
+        stdout.trim()
+    );
+    Ok(stdout)
+}
+
+/// `key` is a keyring in the keys dir, as written by `gpg --export`, `fingerprint` pins
+/// which key of it must have made the signature.
+async fn gpg(cfg: &SignatureConfig, file: &Path, signature: &Path) -> anyhow::Result<()> {
+    let keyring = key_path(cfg)?;
+    let status = run(
+        "gpgv",
+        &[
+            "--status-fd".as_ref(),
+            "1".as_ref(),
+            "--keyring".as_ref(),
+            keyring.as_os_str(),
+            signature.as_os_str(),
+            file.as_os_str(),
+        ],
+    )
+    .await?;
+    let fields: Vec<&str> = status
+        .lines()
+        .find_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+        .map(|rest| rest.split_whitespace().collect())
+        .ok_or_else(|| anyhow::anyhow!("gpgv: no valid signature"))?;
+    if let Some(fingerprint) = &cfg.fingerprint {
+        let fingerprint: String = fingerprint.split_whitespace().collect();
+        // The signing (sub)key comes first, the primary key is the tenth field.
+        let signer = fields.first().copied().unwrap_or_default();
+        let primary = fields.get(9).copied().unwrap_or_default();
+        anyhow::ensure!(
+            signer.eq_ignore_ascii_case(&fingerprint) || primary.eq_ignore_ascii_case(&fingerprint),
+            "gpgv: signed by {} of {}, not by {}",
+            signer,
+            primary,
+            fingerprint
+        );
+    }
+    Ok(())
+}
+
+/// Verifies a sigstore bundle with `cosign`, either by the public `key` in the keys dir or
+/// keyless by the certificate `identity` (a regex) and its OIDC `issuer` against the
+/// `trusted_root`, offline either way.
+async fn cosign(cfg: &SignatureConfig, file: &Path, signature: &Path) -> anyhow::Result<()> {
+    let mut args: Vec<std::ffi::OsString> =
+        vec!["verify-blob".into(), "--bundle".into(), signature.into()];
+    match (&cfg.key, &cfg.identity, &cfg.issuer) {
+        (Some(_), _, _) => {
+            args.extend(["--key".into(), key_path(cfg)?.into(), "--offline".into()]);
+        }
+        (None, Some(identity), Some(issuer)) => {
+            args.extend([
+                "--certificate-identity-regexp".into(),
+                identity.into(),
+                "--certificate-oidc-issuer".into(),
+                issuer.into(),
+                "--offline".into(),
+                "--trusted-root".into(),
+                trusted_root(cfg.trusted_root.as_deref())?.into(),
+            ]);
+        }
+        _ => anyhow::bail!("cosign needs a `key`, or an `identity` and an `issuer`"),
+    }
+    args.push(file.into());
+    run("cosign", &args).await.map(|_| ())
+}
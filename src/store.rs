@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+
+use crate::opt::AppVersion;
+
+/// Installed versions of the apps, each under `<data dir>/store/<name>/<version>/`, so
+/// several of them live side by side and switching between them needs no network access.
+#[derive(Clone, Debug)]
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Store::new(crate::project_dirs().data_dir().join("store"))
+    }
+}
+
+impl Store {
+    pub fn new(dir: impl Into<PathBuf>) -> Store {
+        Store { dir: dir.into() }
+    }
+
+    fn app_dir(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Tags may contain `/`, which is not allowed in a file name, and must not name a dir
+    /// outside of the app dir.
+    pub fn version_dir(&self, name: &str, version: &str) -> anyhow::Result<PathBuf> {
+        let dir = version.replace(['/', '\\'], "_");
+        anyhow::ensure!(
+            !matches!(dir.as_str(), "" | "." | ".."),
+            "invalid version `{}` of {}",
+            version,
+            name
+        );
+        Ok(self.app_dir(name).join(dir))
+    }
+
+    /// Checks the files of `app` are still the installed ones.
+    pub fn verify(&self, app: &AppVersion) -> anyhow::Result<()> {
+        for file in app.files.iter() {
+            let actual = crate::checksum::sha256(file)?;
+            if let Some(expected) = app.checksums.get(file) {
+                anyhow::ensure!(
+                    &actual == expected,
+                    "{} was modified, expected sha256 {}, got {}",
+                    file.display(),
+                    expected,
+                    actual
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn remove(&self, app: &AppVersion) {
+        if let Ok(dir) = self.version_dir(&app.name, &app.version) {
+            tokio::fs::remove_dir_all(dir).await.ok();
+        }
+    }
+
+    /// Removes all versions of `name`.
+    pub async fn remove_all(&self, name: &str) {
+        tokio::fs::remove_dir_all(self.app_dir(name)).await.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_dir_of_tag() {
+        let store = Store::new("/store");
+        let cases = [
+            ("v1.0.0", Some("/store/tool/v1.0.0")),
+            ("release/1.0", Some("/store/tool/release_1.0")),
+            ("../..", Some("/store/tool/.._..")),
+            ("..", None),
+            (".", None),
+            ("", None),
+        ];
+        for (version, expected) in cases {
+            assert_eq!(
+                store.version_dir("tool", version).ok(),
+                expected.map(PathBuf::from),
+                "{}",
+                version
+            );
+        }
+    }
+}
@@ -1,12 +1,87 @@
-pub const APPLE: [&str; 4] = ["macos", "darwin", "mac", "dmg"];
-pub const LINUX: [&str; 1] = ["linux"];
-pub const AMD64: [&str; 4] = ["x64", "x86_64", "amd64", "64bit"];
-pub const ARM64: [&str; 2] = ["aarch64", "arm64"];
+//! How asset names call operating systems, architectures and C libraries, the first
+//! alias of each is the canonical name.
+
+use serde::{Deserialize, Serialize};
+
+pub const APPLE: &[&str] = &["macos", "darwin", "mac", "osx", "apple"];
+pub const LINUX: &[&str] = &["linux"];
+pub const WINDOWS: &[&str] = &["windows", "win", "win64", "win32"];
+pub const AMD64: &[&str] = &["x86_64", "x64", "amd64", "64bit"];
+pub const ARM64: &[&str] = &["aarch64", "arm64", "armv8"];
+/// `armhf` is the Debian name of it, `armv8l` a 32-bit userland on a 64-bit CPU.
+pub const ARMV7: &[&str] = &[
+    "armv7", "armv7l", "armv7a", "armv7hf", "armhf", "armv8l", "arm32",
+];
+/// A plain `arm` is ARMv6, like the `arm-unknown-linux-gnueabihf` target of Rust.
+pub const ARMV6: &[&str] = &["armv6", "armv6l", "armv6hf", "armel", "arm"];
+pub const I686: &[&str] = &[
+    "i686", "i386", "i486", "i586", "386", "x86", "ia32", "32bit",
+];
+pub const RISCV64: &[&str] = &["riscv64", "riscv64gc", "riscv"];
+pub const S390X: &[&str] = &["s390x"];
+pub const PPC64LE: &[&str] = &["ppc64le", "powerpc64le", "ppc64el"];
+
+pub const GNU: &[&str] = &["gnu", "glibc", "gnueabihf", "gnueabi"];
+pub const MUSL: &[&str] = &["musl", "musleabihf", "musleabi"];
+
+/// Every C library assets are linked against.
+pub const LIBC_ALIASES: &[&[&str]] = &[GNU, MUSL, &["msvc"]];
+
+/// Every operating system assets are built for, including those `up` does not run on.
+pub const OS_ALIASES: &[&[&str]] = &[
+    APPLE,
+    LINUX,
+    WINDOWS,
+    &["freebsd"],
+    &["netbsd"],
+    &["openbsd"],
+    &["dragonfly"],
+    &["illumos", "solaris", "sunos"],
+    &["android"],
+    &["ios"],
+    &["wasi", "wasm"],
+];
+
+/// Every architecture assets are built for, including those `up` does not run on.
+pub const ARCH_ALIASES: &[&[&str]] = &[
+    AMD64,
+    ARM64,
+    ARMV7,
+    ARMV6,
+    I686,
+    RISCV64,
+    S390X,
+    PPC64LE,
+    &["ppc64", "powerpc64"],
+    &["mips", "mipsel", "mips64", "mips64el", "mips64le"],
+    &["loong64", "loongarch64"],
+    &["wasm32"],
+];
 
 #[derive(Debug)]
 pub struct System {
     arch: Arch,
     os: OS,
+    libc: Option<Libc>,
+    glibc_version: Option<(u32, u32)>,
+}
+
+/// C libraries of Linux.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, clap::ArgEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Libc {
+    Gnu,
+    Musl,
+}
+
+impl Libc {
+    /// Canonical name, as `sysinfo::LIBC_ALIASES` has it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Libc::Gnu => GNU[0],
+            Libc::Musl => MUSL[0],
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -22,11 +97,74 @@ pub enum OS {
 pub enum Arch {
     amd64,
     aarch64,
+    armv7,
+    armv6,
+    i686,
+    riscv64,
+    s390x,
+    ppc64le,
     Unknown,
 }
 
+impl OS {
+    /// Canonical name, as `sysinfo::OS_ALIASES` has it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OS::Windows => WINDOWS[0],
+            OS::Linux => LINUX[0],
+            OS::Darwin => APPLE[0],
+            OS::Unknown => "unknown",
+        }
+    }
+}
+
+impl Arch {
+    /// Canonical name, as `sysinfo::ARCH_ALIASES` has it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Arch::amd64 => AMD64[0],
+            Arch::aarch64 => ARM64[0],
+            Arch::armv7 => ARMV7[0],
+            Arch::armv6 => ARMV6[0],
+            Arch::i686 => I686[0],
+            Arch::riscv64 => RISCV64[0],
+            Arch::s390x => S390X[0],
+            Arch::ppc64le => PPC64LE[0],
+            Arch::Unknown => "unknown",
+        }
+    }
+
+    /// The arch of `name`, a canonical name or an alias of it, like `uname -m` prints.
+    pub fn from_name(name: &str) -> Option<Arch> {
+        let name = name.to_ascii_lowercase();
+        let aliases = [
+            (Arch::amd64, AMD64),
+            (Arch::aarch64, ARM64),
+            (Arch::armv7, ARMV7),
+            (Arch::armv6, ARMV6),
+            (Arch::i686, I686),
+            (Arch::riscv64, RISCV64),
+            (Arch::s390x, S390X),
+            (Arch::ppc64le, PPC64LE),
+        ];
+        aliases
+            .iter()
+            .find(|(_, aliases)| aliases.contains(&name.as_str()))
+            .map(|(arch, _)| *arch)
+    }
+
+    /// Older archs this one runs the binaries of too, by canonical name.
+    pub fn also_runs(&self) -> &'static [&'static str] {
+        match self {
+            Arch::armv7 => &["armv6"],
+            _ => &[],
+        }
+    }
+}
+
 impl System {
-    pub fn new() -> Self {
+    /// The host, an error if `up` can not tell which assets run on it.
+    pub fn new() -> anyhow::Result<Self> {
         let os = if cfg!(target_os = "linux") {
             OS::Linux
         } else if cfg!(target_os = "macos") {
@@ -34,16 +172,37 @@ impl System {
         } else if cfg!(windows) {
             OS::Windows
         } else {
-            panic!("This platform dose not support yet!");
+            anyhow::bail!("unsupported platform {}", std::env::consts::OS);
         };
-        let arch = if cfg!(target_arch = "x86_64") {
-            Arch::amd64
-        } else if cfg!(target_arch = "aarch64") {
-            Arch::aarch64
-        } else {
-            panic!("This arch dose not support yet!");
+        let arch = detect_arch().ok_or_else(|| {
+            anyhow::anyhow!("unsupported architecture {}", std::env::consts::ARCH)
+        })?;
+        let (libc, glibc_version) = match os {
+            OS::Linux => detect_libc(),
+            _ => (None, None),
         };
-        Self { os, arch }
+        Ok(Self {
+            os,
+            arch,
+            libc,
+            glibc_version,
+        })
+    }
+
+    /// A system other than the host.
+    #[cfg(test)]
+    pub(crate) fn of(
+        os: OS,
+        arch: Arch,
+        libc: Option<Libc>,
+        glibc_version: Option<(u32, u32)>,
+    ) -> System {
+        System {
+            os,
+            arch,
+            libc,
+            glibc_version,
+        }
     }
 
     pub fn os(&self) -> OS {
@@ -53,6 +212,149 @@ impl System {
     pub fn arch(&self) -> Arch {
         self.arch
     }
+
+    /// The C library of the host, `None` if not Linux or it can not be told.
+    pub fn libc(&self) -> Option<Libc> {
+        self.libc
+    }
+
+    /// Version of the glibc of the host, as `(major, minor)`.
+    pub fn glibc_version(&self) -> Option<(u32, u32)> {
+        self.glibc_version
+    }
+
+    /// The Rust target triple of the host, linked against `libc` on Linux, glibc if `None`.
+    pub fn target(&self, libc: Option<Libc>) -> String {
+        let arch = match self.arch {
+            Arch::amd64 => "x86_64",
+            Arch::armv6 => "arm",
+            Arch::riscv64 => "riscv64gc",
+            Arch::ppc64le => "powerpc64le",
+            arch => arch.name(),
+        };
+        let env = match self.arch {
+            Arch::armv7 | Arch::armv6 => "eabihf",
+            _ => "",
+        };
+        match self.os {
+            OS::Linux => format!(
+                "{}-unknown-linux-{}{}",
+                arch,
+                libc.unwrap_or(Libc::Gnu).name(),
+                env
+            ),
+            OS::Darwin => format!("{}-apple-darwin", arch),
+            OS::Windows => format!("{}-pc-windows-msvc", arch),
+            OS::Unknown => format!("{}-unknown-unknown", arch),
+        }
+    }
+}
+
+/// The arch of the machine, which may differ from the one `up` was built for, e.g. an
+/// ARMv6 build on an ARMv7 board.
+fn detect_arch() -> Option<Arch> {
+    #[cfg(unix)]
+    if let Some(arch) = Arch::from_name(nix::sys::utsname::uname().machine()) {
+        // A 64-bit kernel may run a 32-bit userland, like Raspberry Pi OS does, which has
+        // none of the libraries 64-bit binaries need.
+        return Some(match (arch, elf_class("/bin/sh")) {
+            (Arch::aarch64, Some(ElfClass::Elf32)) => Arch::armv7,
+            (Arch::amd64, Some(ElfClass::Elf32)) => Arch::i686,
+            (arch, _) => arch,
+        });
+    }
+    Arch::from_name(std::env::consts::ARCH)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ElfClass {
+    Elf32,
+    Elf64,
+}
+
+/// Whether the executable at `path` is a 32 or a 64-bit one, `None` if it is no ELF file.
+fn elf_class(path: &str) -> Option<ElfClass> {
+    use std::io::Read;
+
+    let mut ident = [0u8; 5];
+    std::fs::File::open(path)
+        .ok()?
+        .read_exact(&mut ident)
+        .ok()?;
+    elf_class_of(&ident)
+}
+
+fn elf_class_of(ident: &[u8]) -> Option<ElfClass> {
+    match ident {
+        [0x7f, b'E', b'L', b'F', 1, ..] => Some(ElfClass::Elf32),
+        [0x7f, b'E', b'L', b'F', 2, ..] => Some(ElfClass::Elf64),
+        _ => None,
+    }
+}
+
+/// The libc of the host, not the one `up` was built against, `up` may be a static musl
+/// build running on a glibc host.
+fn detect_libc() -> (Option<Libc>, Option<(u32, u32)>) {
+    let output = |program: &str, args: &[&str]| {
+        std::process::Command::new(program)
+            .args(args)
+            .output()
+            .ok()
+            .map(|output| {
+                let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                text.push_str(&String::from_utf8_lossy(&output.stderr));
+                text
+            })
+    };
+    let musl_loader = std::fs::read_dir("/lib")
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .any(|entry| entry.file_name().to_string_lossy().starts_with("ld-musl-"))
+        })
+        .unwrap_or(false);
+    let getconf = output("getconf", &["GNU_LIBC_VERSION"]);
+    // ldd is only asked when neither tells.
+    libc_of(getconf.as_deref(), musl_loader, || {
+        output("ldd", &["--version"])
+    })
+}
+
+/// The libc told by `getconf GNU_LIBC_VERSION`, else by a musl loader in /lib, else by
+/// `ldd --version`.
+///
+/// glibc hosts may have the musl package and its loader installed, getconf comes first.
+fn libc_of(
+    getconf: Option<&str>,
+    musl_loader: bool,
+    ldd: impl FnOnce() -> Option<String>,
+) -> (Option<Libc>, Option<(u32, u32)>) {
+    // `glibc 2.36`, musl has no such variable.
+    if let Some(version) =
+        getconf.and_then(|text| text.trim().strip_prefix("glibc ").and_then(parse_version))
+    {
+        return (Some(Libc::Gnu), Some(version));
+    }
+    if musl_loader {
+        return (Some(Libc::Musl), None);
+    }
+    // `ldd (GNU libc) 2.17` or `ldd (Debian GLIBC 2.36-9) 2.36`, musl prints `musl libc`.
+    match ldd() {
+        Some(text) if text.contains("musl") => (Some(Libc::Musl), None),
+        Some(text) if text.contains("GNU") || text.contains("GLIBC") => {
+            let first = text.lines().next().unwrap_or_default();
+            let version = first.split_whitespace().last().and_then(parse_version);
+            (Some(Libc::Gnu), version)
+        }
+        _ => (None, None),
+    }
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.split('-').next()?.parse().ok()?;
+    Some((major, minor))
 }
 
 // pub struct SysDetector {}
@@ -61,3 +363,83 @@ impl System {
 //         for
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elf_classes() {
+        let cases: [(&[u8], _); 4] = [
+            (b"\x7fELF\x01", Some(ElfClass::Elf32)),
+            (b"\x7fELF\x02\x01\x01", Some(ElfClass::Elf64)),
+            (b"#!/bin/sh", None),
+            (b"\x7fELF", None),
+        ];
+        for (ident, expected) in cases {
+            assert_eq!(elf_class_of(ident), expected, "{:?}", ident);
+        }
+        assert_eq!(elf_class("/bin/sh").is_some(), cfg!(target_os = "linux"));
+    }
+
+    #[test]
+    fn libc_precedence() {
+        let gnu_ldd = "ldd (Debian GLIBC 2.36-9) 2.36\nCopyright (C) 2022";
+        let musl_ldd = "musl libc (x86_64)\nVersion 1.2.4";
+        let cases = [
+            // a glibc host with the musl package installed
+            (
+                Some("glibc 2.36\n"),
+                true,
+                None,
+                Some(Libc::Gnu),
+                Some((2, 36)),
+            ),
+            (
+                Some("glibc 2.31"),
+                false,
+                Some(musl_ldd),
+                Some(Libc::Gnu),
+                Some((2, 31)),
+            ),
+            (
+                Some("getconf: Unrecognized variable"),
+                true,
+                Some(gnu_ldd),
+                Some(Libc::Musl),
+                None,
+            ),
+            (None, true, None, Some(Libc::Musl), None),
+            (None, false, Some(gnu_ldd), Some(Libc::Gnu), Some((2, 36))),
+            (None, false, Some(musl_ldd), Some(Libc::Musl), None),
+            (None, false, None, None, None),
+        ];
+        for (getconf, musl_loader, ldd, libc, version) in cases {
+            let ldd = || ldd.map(String::from);
+            assert_eq!(
+                libc_of(getconf, musl_loader, ldd),
+                (libc, version),
+                "{:?} {}",
+                getconf,
+                musl_loader
+            );
+        }
+    }
+
+    #[test]
+    fn parse_glibc_versions() {
+        let cases = [
+            ("2.36", Some((2, 36))),
+            ("2.17\n", Some((2, 17))),
+            ("2.36-9", Some((2, 36))),
+            ("2.31-0ubuntu9.9", Some((2, 31))),
+            ("2.28.1", Some((2, 28))),
+            ("2", None),
+            ("musl", None),
+            ("", None),
+        ];
+        for (version, expected) in cases {
+            assert_eq!(parse_version(version), expected, "{}", version);
+        }
+    }
+}
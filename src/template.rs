@@ -0,0 +1,194 @@
+//! `asset_name` and `url` of an app may be minijinja templates, rendered for the release
+//! and the host, e.g. `tool-{{ version }}-{{ target }}.tar.gz`.
+
+use std::sync::{Arc, Mutex};
+
+use minijinja::value::{Object, Value};
+use minijinja::{Environment, Error, State};
+use serde::Serialize;
+
+use crate::matcher::{self, Preferences};
+use crate::sysinfo::System;
+
+/// Variables of a template. Those of the host are undefined if `up` can not tell them, a
+/// template using them fails to render rather than name an asset which does not exist.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Vars {
+    /// The tag without a leading `v`, `1.2.0` of `v1.2.0`.
+    pub version: String,
+    pub tag: String,
+    /// Canonical names of `sysinfo`, `linux` and `x86_64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<&'static str>,
+    /// The Rust target triple of the host, `x86_64-unknown-linux-musl`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// The libc wanted on Linux.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub libc: Option<&'static str>,
+}
+
+impl Vars {
+    /// Variables of the release `tag` on `system`, with the libc `prefs` want.
+    pub fn new(tag: &str, system: Option<&System>, prefs: &Preferences) -> Vars {
+        let libc = system.and_then(|system| matcher::wanted_libc(system, prefs));
+        Vars {
+            version: tag.trim_start_matches('v').to_string(),
+            tag: tag.to_string(),
+            os: system.map(|system| system.os().name()),
+            arch: system.map(|system| system.arch().name()),
+            target: system.map(|system| system.target(libc)),
+            libc: libc.map(|libc| libc.name()),
+        }
+    }
+}
+
+/// Whether `s` is a template rather than a plain name.
+pub fn is_template(s: &str) -> bool {
+    s.contains("{{") || s.contains("{%")
+}
+
+/// Renders `template` with `vars`, an error if it uses a variable which is undefined.
+pub fn render(template: &str, vars: &Vars) -> anyhow::Result<String> {
+    let mut env = Environment::new();
+    env.add_filter("goos", goos);
+    env.add_filter("goarch", goarch);
+    env.add_filter("goarm", goarm);
+    let invalid =
+        |err: Error| anyhow::anyhow!(err).context(format!("Invalid template {}", template));
+    env.add_template("template", template).map_err(invalid)?;
+    let compiled = env.get_template("template").map_err(invalid)?;
+    let strict = Strict {
+        vars: Value::from_serializable(vars),
+        undefined: Arc::default(),
+    };
+    let undefined = strict.undefined.clone();
+    let rendered = compiled
+        .render(Value::from_object(strict))
+        .map_err(invalid)?;
+    let undefined = undefined.lock().unwrap();
+    anyhow::ensure!(
+        undefined.is_empty(),
+        "Can not render {}, undefined here: {}",
+        template,
+        undefined.join(", ")
+    );
+    Ok(rendered)
+}
+
+/// `Vars` recording the names a template looks up but they do not have, minijinja renders
+/// those empty.
+#[derive(Debug)]
+struct Strict {
+    vars: Value,
+    undefined: Arc<Mutex<Vec<String>>>,
+}
+
+impl std::fmt::Display for Strict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.vars)
+    }
+}
+
+impl Object for Strict {
+    fn get_attr(&self, name: &str) -> Option<Value> {
+        let value = self
+            .vars
+            .get_attr(name)
+            .ok()
+            .filter(|value| !value.is_undefined());
+        if value.is_none() {
+            let mut undefined = self.undefined.lock().unwrap();
+            if !undefined.iter().any(|undefined| undefined == name) {
+                undefined.push(name.to_string());
+            }
+        }
+        value
+    }
+}
+
+/// The os as Go and goreleaser spell it, `darwin` for `macos`.
+// Filters return the `Result` minijinja wants, however large its error is.
+#[allow(clippy::result_large_err)]
+fn goos(_state: &State, os: String) -> Result<String, Error> {
+    Ok(match os.as_str() {
+        "macos" => "darwin".to_string(),
+        _ => os,
+    })
+}
+
+/// The arch as Go and goreleaser spell it, `amd64` for `x86_64`, `arm64` for `aarch64`,
+/// `arm` for `armv7` and `armv6`, which `goarm` tells apart.
+#[allow(clippy::result_large_err)]
+fn goarch(_state: &State, arch: String) -> Result<String, Error> {
+    Ok(match arch.as_str() {
+        "x86_64" => "amd64".to_string(),
+        "aarch64" => "arm64".to_string(),
+        "i686" => "386".to_string(),
+        "armv7" | "armv6" => "arm".to_string(),
+        _ => arch,
+    })
+}
+
+/// The ARM version as `GOARM` and goreleaser spell it, `7` for `armv7`, empty if not ARM.
+#[allow(clippy::result_large_err)]
+fn goarm(_state: &State, arch: String) -> Result<String, Error> {
+    Ok(match arch.as_str() {
+        "armv7" => "7".to_string(),
+        "armv6" => "6".to_string(),
+        _ => String::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_templates() {
+        let vars = |arch| Vars {
+            version: "1.2.0".to_string(),
+            tag: "v1.2.0".to_string(),
+            os: Some("linux"),
+            arch,
+            target: None,
+            libc: None,
+        };
+        let cases = [
+            (
+                "tool-{{ version }}-{{ os }}-{{ arch }}.tar.gz",
+                Some("x86_64"),
+                Some("tool-1.2.0-linux-x86_64.tar.gz"),
+            ),
+            (
+                "tool_{{ version }}_{{ os | goos }}_{{ arch | goarch }}.tar.gz",
+                Some("x86_64"),
+                Some("tool_1.2.0_linux_amd64.tar.gz"),
+            ),
+            (
+                "tool_{{ os }}_{{ arch | goarch }}v{{ arch | goarm }}.tar.gz",
+                Some("armv7"),
+                Some("tool_linux_armv7.tar.gz"),
+            ),
+            (
+                "tool_{{ arch | goarch }}{{ arch | goarm }}",
+                Some("armv6"),
+                Some("tool_arm6"),
+            ),
+            ("tool-{{ tag }}-{{ target }}.tar.gz", Some("x86_64"), None),
+            ("tool-{{ tag }}-{{ arch }}.tar.gz", None, None),
+            ("tool-{{ verison }}.tar.gz", Some("x86_64"), None),
+            ("tool-{{ version", Some("x86_64"), None),
+        ];
+        for (template, arch, expected) in cases {
+            assert_eq!(
+                render(template, &vars(arch)).ok().as_deref(),
+                expected,
+                "{}",
+                template
+            );
+        }
+    }
+}
@@ -18,10 +18,32 @@ pub async fn choose<T: Choosable>(options: &[T], desc: impl AsRef<str>) -> anyho
     // println!("{}", desc.as_ref());
     let chosen = Select::new()
         .with_prompt(desc.as_ref())
-        .items(&options)
+        .items(options)
         .default(0)
         .interact_opt()?
         .ok_or_else(|| anyhow::anyhow!("cancelled."))?;
     // println!("> {}", options[chosen].to_string());
     Ok(&options[chosen])
 }
+
+/// Like `choose`, but with an extra last item to load more options if `more`.
+///
+/// `None` if that one is chosen.
+pub async fn choose_or_more<T: Choosable>(
+    options: &[T],
+    desc: impl AsRef<str>,
+    more: bool,
+) -> anyhow::Result<Option<&T>> {
+    if !more {
+        return choose(options, desc).await.map(Some);
+    }
+    let mut items: Vec<String> = options.iter().map(ToString::to_string).collect();
+    items.push("... load more".into());
+    let chosen = Select::new()
+        .with_prompt(desc.as_ref())
+        .items(&items)
+        .default(0)
+        .interact_opt()?
+        .ok_or_else(|| anyhow::anyhow!("cancelled."))?;
+    Ok(options.get(chosen))
+}